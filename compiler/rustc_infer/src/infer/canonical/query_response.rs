@@ -594,10 +594,24 @@ pub fn make_query_region_constraints<'tcx>(
     outlives_obligations: impl Iterator<Item = (Ty<'tcx>, ty::Region<'tcx>)>,
     region_constraints: &RegionConstraintData<'tcx>,
 ) -> QueryRegionConstraints<'tcx> {
-    let RegionConstraintData { constraints, verifys, givens, member_constraints } =
-        region_constraints;
+    let RegionConstraintData {
+        constraints,
+        verifys,
+        verify_subregions,
+        // Pins only change how errors are reported; the constraints they
+        // stand for are in `constraints`.
+        pins: _,
+        strict_subregions,
+        // Roots only let resolution skip work; the caller resolves all of
+        // the constraints returned here anyway.
+        roots: _,
+        givens,
+        member_constraints,
+    } = region_constraints;
 
     assert!(verifys.is_empty());
+    assert!(verify_subregions.is_empty());
+    assert!(strict_subregions.is_empty());
     assert!(givens.is_empty());
 
     let outlives: Vec<_> = constraints
@@ -71,7 +71,7 @@
     subst::{GenericArgKind, Subst, SubstsRef},
     Region, Ty, TyCtxt, TypeFoldable,
 };
-use rustc_span::{sym, BytePos, DesugaringKind, Pos, Span};
+use rustc_span::{sym, BytePos, DesugaringKind, Pos, Span, DUMMY_SP};
 use rustc_target::spec::abi;
 use std::ops::ControlFlow;
 use std::{cmp, fmt, iter};
@@ -90,7 +90,18 @@ pub(super) fn note_and_explain_region(
     region: ty::Region<'tcx>,
     suffix: &str,
 ) {
-    let (description, span) = match *region {
+    if let Some((description, span)) = msg_span_from_region(tcx, region) {
+        emit_msg_span(err, prefix, description, span, suffix);
+    }
+}
+
+/// Describes `region` the way `note_and_explain_region` does, or returns
+/// `None` for placeholders, which it does not explain.
+fn msg_span_from_region(
+    tcx: TyCtxt<'tcx>,
+    region: ty::Region<'tcx>,
+) -> Option<(String, Option<Span>)> {
+    let msg_span = match *region {
         ty::ReEarlyBound(_) | ty::ReFree(_) | ty::ReStatic => {
             msg_span_from_free_region(tcx, region)
         }
@@ -100,7 +111,7 @@ pub(super) fn note_and_explain_region(
         // uh oh, hope no user ever sees THIS
         ty::ReEmpty(ui) => (format!("the empty lifetime in universe {:?}", ui), None),
 
-        ty::RePlaceholder(_) => return,
+        ty::RePlaceholder(_) => return None,
 
         // FIXME(#13998) RePlaceholder should probably print like
         // ReFree rather than dumping Debug output on the user.
@@ -111,8 +122,7 @@ pub(super) fn note_and_explain_region(
             (format!("lifetime {:?}", region), None)
         }
     };
-
-    emit_msg_span(err, prefix, description, span, suffix);
+    Some(msg_span)
 }
 
 pub(super) fn note_and_explain_free_region(
@@ -365,14 +375,14 @@ pub fn report_region_errors(&self, errors: &Vec<RegionResolutionError<'tcx>>) {
                         );
                     }
 
-                    RegionResolutionError::SubSupConflict(
-                        _,
+                    RegionResolutionError::SubSupConflict {
                         var_origin,
                         sub_origin,
-                        sub_r,
+                        sub_region: sub_r,
                         sup_origin,
-                        sup_r,
-                    ) => {
+                        sup_region: sup_r,
+                        ..
+                    } => {
                         if sub_r.is_placeholder() {
                             self.report_placeholder_failure(sub_origin, sub_r, sup_r).emit();
                         } else if sup_r.is_placeholder() {
@@ -417,6 +427,50 @@ pub fn report_region_errors(&self, errors: &Vec<RegionResolutionError<'tcx>>) {
                         )
                         .emit();
                     }
+
+                    RegionResolutionError::PinViolation(origin, _, expected, actual) => {
+                        let mut err = self.tcx.sess.struct_span_err(
+                            origin.span(),
+                            "lifetime does not match the lifetime it is required to be",
+                        );
+                        note_and_explain_region(
+                            self.tcx,
+                            &mut err,
+                            "the lifetime is required to be ",
+                            expected,
+                            "...",
+                        );
+                        note_and_explain_region(
+                            self.tcx,
+                            &mut err,
+                            "...but must be valid for ",
+                            actual,
+                            "",
+                        );
+                        err.emit();
+                    }
+
+                    RegionResolutionError::StrictnessViolation(origin, sub, _) => {
+                        let mut err = self.tcx.sess.struct_span_err(
+                            origin.span(),
+                            "lifetime must be strictly shorter than the one it is contained in",
+                        );
+                        note_and_explain_region(
+                            self.tcx,
+                            &mut err,
+                            "both lifetimes were inferred to be ",
+                            sub,
+                            "",
+                        );
+                        err.emit();
+                    }
+
+                    RegionResolutionError::Timeout { iterations, node_count } => {
+                        self.tcx.sess.err(&format!(
+                            "region inference gave up after {} steps over {} region variables",
+                            iterations, node_count,
+                        ));
+                    }
                 }
             }
         }
@@ -453,9 +507,12 @@ fn process_errors(
         let is_bound_failure = |e: &RegionResolutionError<'tcx>| match *e {
             RegionResolutionError::GenericBoundFailure(..) => true,
             RegionResolutionError::ConcreteFailure(..)
-            | RegionResolutionError::SubSupConflict(..)
+            | RegionResolutionError::SubSupConflict { .. }
             | RegionResolutionError::UpperBoundUniverseConflict(..)
-            | RegionResolutionError::MemberConstraintFailure { .. } => false,
+            | RegionResolutionError::MemberConstraintFailure { .. }
+            | RegionResolutionError::PinViolation(..)
+            | RegionResolutionError::StrictnessViolation(..)
+            | RegionResolutionError::Timeout { .. } => false,
         };
 
         let mut errors = if errors.iter().all(|e| is_bound_failure(e)) {
@@ -468,9 +525,12 @@ fn process_errors(
         errors.sort_by_key(|u| match *u {
             RegionResolutionError::ConcreteFailure(ref sro, _, _) => sro.span(),
             RegionResolutionError::GenericBoundFailure(ref sro, _, _) => sro.span(),
-            RegionResolutionError::SubSupConflict(_, ref rvo, _, _, _, _) => rvo.span(),
+            RegionResolutionError::SubSupConflict { ref var_origin, .. } => var_origin.span(),
             RegionResolutionError::UpperBoundUniverseConflict(_, ref rvo, _, _, _) => rvo.span(),
             RegionResolutionError::MemberConstraintFailure { span, .. } => span,
+            RegionResolutionError::PinViolation(ref sro, ..) => sro.span(),
+            RegionResolutionError::StrictnessViolation(ref sro, ..) => sro.span(),
+            RegionResolutionError::Timeout { .. } => DUMMY_SP,
         });
         errors
     }
@@ -2403,6 +2463,20 @@ fn report_inference_failure(
         &self,
         var_origin: RegionVariableOrigin,
     ) -> DiagnosticBuilder<'tcx> {
+        struct_span_err!(
+            self.tcx.sess,
+            var_origin.span(),
+            E0495,
+            "cannot infer an appropriate lifetime{} due to conflicting requirements",
+            self.describe_var_origin(&var_origin)
+        )
+    }
+
+    /// Describes what a region variable was created for, as a suffix to
+    /// "cannot infer an appropriate lifetime" (so it is empty for
+    /// `MiscVariable`, and otherwise starts with a space). This is the one
+    /// place where origins are phrased, so that all region errors agree.
+    pub fn describe_var_origin(&self, var_origin: &RegionVariableOrigin) -> String {
         let br_string = |br: ty::BoundRegionKind| {
             let mut s = match br {
                 ty::BrNamed(_, name) => name.to_string(),
@@ -2413,7 +2487,7 @@ fn report_inference_failure(
             }
             s
         };
-        let var_description = match var_origin {
+        match *var_origin {
             infer::MiscVariable(_) => String::new(),
             infer::PatternRegion(_) => " for pattern".to_string(),
             infer::AddrOfRegion(_) => " for borrow expression".to_string(),
@@ -2436,15 +2510,71 @@ fn report_inference_failure(
                 format!(" for capture of `{}` by closure", var_name)
             }
             infer::Nll(..) => bug!("NLL variable found in lexical phase"),
-        };
+        }
+    }
 
-        struct_span_err!(
-            self.tcx.sess,
-            var_origin.span(),
-            E0495,
-            "cannot infer an appropriate lifetime{} due to conflicting requirements",
-            var_description
-        )
+    /// Summarizes `error` in a single sentence, without emitting anything.
+    /// This uses the same descriptions of regions and region variables as
+    /// the diagnostics emitted by `report_region_errors`, but leaves out the
+    /// notes on why each requirement arose.
+    pub fn explain_region_error(&self, error: &RegionResolutionError<'tcx>) -> String {
+        let describe = |region| match msg_span_from_region(self.tcx, region) {
+            Some((description, _)) => description,
+            None => format!("lifetime {:?}", region),
+        };
+        match *error {
+            RegionResolutionError::ConcreteFailure(_, sub, sup) => {
+                format!("{} must outlive {}, but it does not", describe(sup), describe(sub))
+            }
+            RegionResolutionError::GenericBoundFailure(_, ref kind, sub) => {
+                let kind = match *kind {
+                    GenericKind::Param(ref p) => format!("the parameter type `{}`", p),
+                    GenericKind::Projection(ref p) => format!("the associated type `{}`", p),
+                };
+                format!("{} may not live long enough: it must be valid for {}", kind, describe(sub))
+            }
+            RegionResolutionError::SubSupConflict {
+                var_origin,
+                sub_region: sub_r,
+                sup_region: sup_r,
+                ..
+            } => format!(
+                "cannot infer an appropriate lifetime{}: it must be valid for {}, \
+                 but cannot outlive {}",
+                self.describe_var_origin(&var_origin),
+                describe(sub_r),
+                describe(sup_r),
+            ),
+            RegionResolutionError::UpperBoundUniverseConflict(_, var_origin, _, _, sup_r) => {
+                format!(
+                    "cannot infer an appropriate lifetime{}: it cannot outlive {}, \
+                     which it cannot name",
+                    self.describe_var_origin(&var_origin),
+                    describe(sup_r),
+                )
+            }
+            RegionResolutionError::MemberConstraintFailure { hidden_ty, member_region, .. } => {
+                format!(
+                    "hidden type `{}` captures {}, which does not appear in bounds",
+                    self.resolve_vars_if_possible(hidden_ty),
+                    describe(member_region),
+                )
+            }
+            RegionResolutionError::PinViolation(_, _, expected, actual) => format!(
+                "the lifetime is required to be {}, but must be valid for {}",
+                describe(expected),
+                describe(actual),
+            ),
+            RegionResolutionError::StrictnessViolation(_, sub, _) => format!(
+                "a lifetime must be strictly shorter than the one it is contained in, \
+                 but both are {}",
+                describe(sub),
+            ),
+            RegionResolutionError::Timeout { iterations, node_count } => format!(
+                "region inference gave up after {} steps over {} region variables",
+                iterations, node_count,
+            ),
+        }
     }
 }
 
@@ -63,9 +63,12 @@ pub fn try_report(&self) -> Option<ErrorReported> {
     pub fn regions(&self) -> Option<(Span, ty::Region<'tcx>, ty::Region<'tcx>)> {
         match (&self.error, self.regions) {
             (Some(ConcreteFailure(origin, sub, sup)), None) => Some((origin.span(), sub, sup)),
-            (Some(SubSupConflict(_, _, origin, sub, _, sup)), None) => {
-                Some((origin.span(), sub, sup))
-            }
+            (
+                Some(SubSupConflict {
+                    sub_origin: origin, sub_region: sub, sup_region: sup, ..
+                }),
+                None,
+            ) => Some((origin.span(), sub, sup)),
             (None, Some((span, sub, sup))) => Some((span, sub, sup)),
             _ => None,
         }
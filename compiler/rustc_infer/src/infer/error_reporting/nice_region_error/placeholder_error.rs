@@ -27,14 +27,13 @@ pub(super) fn try_report_placeholder_conflict(&self) -> Option<DiagnosticBuilder
             ///////////////////////////////////////////////////////////////////////////
             // Check for errors from comparing trait failures -- first
             // with two placeholders, then with one.
-            Some(RegionResolutionError::SubSupConflict(
-                vid,
-                _,
-                SubregionOrigin::Subtype(box TypeTrace { cause, values }),
-                sub_placeholder @ ty::RePlaceholder(_),
-                _,
-                sup_placeholder @ ty::RePlaceholder(_),
-            )) => self.try_report_trait_placeholder_mismatch(
+            Some(RegionResolutionError::SubSupConflict {
+                var: vid,
+                sub_origin: SubregionOrigin::Subtype(box TypeTrace { cause, values }),
+                sub_region: sub_placeholder @ ty::RePlaceholder(_),
+                sup_region: sup_placeholder @ ty::RePlaceholder(_),
+                ..
+            }) => self.try_report_trait_placeholder_mismatch(
                 Some(self.tcx().mk_region(ty::ReVar(*vid))),
                 cause,
                 Some(sub_placeholder),
@@ -42,14 +41,12 @@ pub(super) fn try_report_placeholder_conflict(&self) -> Option<DiagnosticBuilder
                 values,
             ),
 
-            Some(RegionResolutionError::SubSupConflict(
-                vid,
-                _,
-                SubregionOrigin::Subtype(box TypeTrace { cause, values }),
-                sub_placeholder @ ty::RePlaceholder(_),
-                _,
-                _,
-            )) => self.try_report_trait_placeholder_mismatch(
+            Some(RegionResolutionError::SubSupConflict {
+                var: vid,
+                sub_origin: SubregionOrigin::Subtype(box TypeTrace { cause, values }),
+                sub_region: sub_placeholder @ ty::RePlaceholder(_),
+                ..
+            }) => self.try_report_trait_placeholder_mismatch(
                 Some(self.tcx().mk_region(ty::ReVar(*vid))),
                 cause,
                 Some(sub_placeholder),
@@ -57,14 +54,12 @@ pub(super) fn try_report_placeholder_conflict(&self) -> Option<DiagnosticBuilder
                 values,
             ),
 
-            Some(RegionResolutionError::SubSupConflict(
-                vid,
-                _,
-                SubregionOrigin::Subtype(box TypeTrace { cause, values }),
-                _,
-                _,
-                sup_placeholder @ ty::RePlaceholder(_),
-            )) => self.try_report_trait_placeholder_mismatch(
+            Some(RegionResolutionError::SubSupConflict {
+                var: vid,
+                sub_origin: SubregionOrigin::Subtype(box TypeTrace { cause, values }),
+                sup_region: sup_placeholder @ ty::RePlaceholder(_),
+                ..
+            }) => self.try_report_trait_placeholder_mismatch(
                 Some(self.tcx().mk_region(ty::ReVar(*vid))),
                 cause,
                 None,
@@ -72,14 +67,12 @@ pub(super) fn try_report_placeholder_conflict(&self) -> Option<DiagnosticBuilder
                 values,
             ),
 
-            Some(RegionResolutionError::SubSupConflict(
-                vid,
-                _,
-                _,
-                _,
-                SubregionOrigin::Subtype(box TypeTrace { cause, values }),
-                sup_placeholder @ ty::RePlaceholder(_),
-            )) => self.try_report_trait_placeholder_mismatch(
+            Some(RegionResolutionError::SubSupConflict {
+                var: vid,
+                sup_origin: SubregionOrigin::Subtype(box TypeTrace { cause, values }),
+                sup_region: sup_placeholder @ ty::RePlaceholder(_),
+                ..
+            }) => self.try_report_trait_placeholder_mismatch(
                 Some(self.tcx().mk_region(ty::ReVar(*vid))),
                 cause,
                 None,
@@ -21,14 +21,14 @@ pub(super) fn try_report_static_impl_trait(&self) -> Option<ErrorReported> {
         debug!("try_report_static_impl_trait(error={:?})", self.error);
         let tcx = self.tcx();
         let (var_origin, sub_origin, sub_r, sup_origin, sup_r) = match self.error.as_ref()? {
-            RegionResolutionError::SubSupConflict(
-                _,
+            RegionResolutionError::SubSupConflict {
                 var_origin,
                 sub_origin,
-                sub_r,
+                sub_region: sub_r,
                 sup_origin,
-                sup_r,
-            ) if **sub_r == RegionKind::ReStatic => {
+                sup_region: sup_r,
+                ..
+            } if **sub_r == RegionKind::ReStatic => {
                 (var_origin, sub_origin, sub_r, sup_origin, sup_r)
             }
             RegionResolutionError::ConcreteFailure(
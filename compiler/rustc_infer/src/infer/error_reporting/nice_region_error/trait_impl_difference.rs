@@ -18,14 +18,12 @@ impl<'a, 'tcx> NiceRegionError<'a, 'tcx> {
     pub(super) fn try_report_impl_not_conforming_to_trait(&self) -> Option<ErrorReported> {
         if let Some(ref error) = self.error {
             debug!("try_report_impl_not_conforming_to_trait {:?}", error);
-            if let RegionResolutionError::SubSupConflict(
-                _,
+            if let RegionResolutionError::SubSupConflict {
                 var_origin,
                 sub_origin,
-                _sub,
                 sup_origin,
-                _sup,
-            ) = error.clone()
+                ..
+            } = error.clone()
             {
                 if let (&Subtype(ref sup_trace), &Subtype(ref sub_trace)) =
                     (&sup_origin, &sub_origin)
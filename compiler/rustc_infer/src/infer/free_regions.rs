@@ -5,7 +5,35 @@
 
 use rustc_data_structures::transitive_relation::TransitiveRelation;
 use rustc_hir::def_id::DefId;
+use rustc_middle::ty::error::TypeError;
+use rustc_middle::ty::relate::RelateResult;
 use rustc_middle::ty::{self, Lift, Region, TyCtxt};
+use std::cmp;
+
+/// The ordering on concrete regions used by lexical region resolution.
+/// `RegionRelations` implements the usual one; other implementations can
+/// be passed to `lexical_region_resolve::resolve_in_lattice`, e.g. to try
+/// out new kinds of regions without touching the resolver itself.
+pub trait RegionLattice<'tcx> {
+    /// Returns the least-upper-bound of the concrete regions `a` and `b`.
+    fn lub_concrete_regions(&self, a: Region<'tcx>, b: Region<'tcx>) -> Region<'tcx>;
+
+    /// True if `a <= b`. By default, this holds if `b` is the LUB of the
+    /// two.
+    fn sub_concrete_regions(&self, a: Region<'tcx>, b: Region<'tcx>) -> bool {
+        self.lub_concrete_regions(a, b) == b
+    }
+}
+
+impl<'a, 'tcx> RegionLattice<'tcx> for RegionRelations<'a, 'tcx> {
+    fn lub_concrete_regions(&self, a: Region<'tcx>, b: Region<'tcx>) -> Region<'tcx> {
+        RegionRelations::lub_concrete_regions(self, a, b)
+    }
+
+    fn sub_concrete_regions(&self, a: Region<'tcx>, b: Region<'tcx>) -> bool {
+        RegionRelations::sub_concrete_regions(self, a, b)
+    }
+}
 
 /// Combines a `FreeRegionMap` and a `TyCtxt`.
 ///
@@ -29,6 +57,242 @@ pub fn new(tcx: TyCtxt<'tcx>, context: DefId, free_regions: &'a FreeRegionMap<'t
     pub fn lub_free_regions(&self, r_a: Region<'tcx>, r_b: Region<'tcx>) -> Region<'tcx> {
         self.free_regions.lub_free_regions(self.tcx, r_a, r_b)
     }
+
+    /// Like `lub_concrete_regions`, but `a` and `b` may be inference
+    /// variables: each one is first replaced with the region `resolve`
+    /// returns for it, e.g. its value so far. This allows combining regions
+    /// while resolution is still under way. `resolve` must not return a
+    /// variable.
+    pub fn lub_regions_resolving(
+        &self,
+        a: Region<'tcx>,
+        b: Region<'tcx>,
+        resolve: &dyn Fn(ty::RegionVid) -> Region<'tcx>,
+    ) -> Region<'tcx> {
+        let resolve = |r: Region<'tcx>| if let ty::ReVar(vid) = *r { resolve(vid) } else { r };
+        self.lub_concrete_regions(resolve(a), resolve(b))
+    }
+
+    /// The GLB analog of `lub_regions_resolving`; see `try_glb_concrete`.
+    pub fn try_glb_regions_resolving(
+        &self,
+        a: Region<'tcx>,
+        b: Region<'tcx>,
+        resolve: &dyn Fn(ty::RegionVid) -> Region<'tcx>,
+    ) -> RelateResult<'tcx, Region<'tcx>> {
+        let resolve = |r: Region<'tcx>| if let ty::ReVar(vid) = *r { resolve(vid) } else { r };
+        self.try_glb_concrete(resolve(a), resolve(b))
+    }
+
+    /// Returns the least-upper-bound of `a` and `b`; i.e., the
+    /// smallest region `c` such that `a <= c` and `b <= c`.
+    ///
+    /// Neither `a` nor `b` may be an inference variable (hence the
+    /// term "concrete regions").
+    pub fn lub_concrete_regions(&self, a: Region<'tcx>, b: Region<'tcx>) -> Region<'tcx> {
+        let r = match (a, b) {
+            (&ty::ReLateBound(..), _)
+            | (_, &ty::ReLateBound(..))
+            | (&ty::ReErased, _)
+            | (_, &ty::ReErased)
+            | (&ty::ReVar(_), _)
+            | (_, &ty::ReVar(_)) => {
+                bug!("cannot relate region: LUB({:?}, {:?})", a, b);
+            }
+
+            (&ty::ReStatic, _) | (_, &ty::ReStatic) => {
+                // nothing lives longer than `'static`
+                self.tcx.lifetimes.re_static
+            }
+
+            (&ty::ReEmpty(_), r @ (ty::ReEarlyBound(_) | ty::ReFree(_)))
+            | (r @ (ty::ReEarlyBound(_) | ty::ReFree(_)), &ty::ReEmpty(_)) => {
+                // All empty regions are less than early-bound, free,
+                // and scope regions.
+                r
+            }
+
+            (&ty::ReEmpty(a_ui), &ty::ReEmpty(b_ui)) => {
+                // Empty regions are ordered according to the universe
+                // they are associated with.
+                let ui = a_ui.min(b_ui);
+                self.tcx.mk_region(ty::ReEmpty(ui))
+            }
+
+            (&ty::ReEmpty(empty_ui), &ty::RePlaceholder(placeholder))
+            | (&ty::RePlaceholder(placeholder), &ty::ReEmpty(empty_ui)) => {
+                // If this empty region is from a universe that can
+                // name the placeholder, then the placeholder is
+                // larger; otherwise, the only ancestor is `'static`.
+                if empty_ui.can_name(placeholder.universe) {
+                    self.tcx.mk_region(ty::RePlaceholder(placeholder))
+                } else {
+                    self.tcx.lifetimes.re_static
+                }
+            }
+
+            // There are no scope regions left to relate through a scope
+            // tree: everything that lives shorter than the fn body is
+            // some empty region, so the only enclosing regions to look
+            // for are other free regions, which `lub_free_regions` finds
+            // through the full outlives relation before settling for
+            // `'static`.
+            (&ty::ReEarlyBound(_) | &ty::ReFree(_), &ty::ReEarlyBound(_) | &ty::ReFree(_)) => {
+                self.lub_free_regions(a, b)
+            }
+
+            // For these types, we cannot define any additional
+            // relationship:
+            (&ty::RePlaceholder(..), _) | (_, &ty::RePlaceholder(..)) => {
+                if a == b {
+                    a
+                } else {
+                    self.tcx.lifetimes.re_static
+                }
+            }
+        };
+
+        debug!("lub_concrete_regions({:?}, {:?}) = {:?}", a, b, r);
+
+        r
+    }
+
+    /// True if `a <= b`, but not defined over inference variables.
+    pub fn sub_concrete_regions(&self, a: Region<'tcx>, b: Region<'tcx>) -> bool {
+        let tcx = self.tcx;
+        let sub_free_regions = |r1, r2| self.free_regions.sub_free_regions(tcx, r1, r2);
+
+        // Check for the case where we know that `'b: 'static` -- in that case,
+        // `a <= b` for all `a`.
+        let b_free_or_static = self.free_regions.is_free_or_static(b);
+        if b_free_or_static && sub_free_regions(tcx.lifetimes.re_static, b) {
+            return true;
+        }
+
+        // If both `a` and `b` are free, consult the declared
+        // relationships.  Note that this can be more precise than the
+        // `lub` relationship defined below, since sometimes the "lub"
+        // is actually the `postdom_upper_bound` (see
+        // `TransitiveRelation` for more details).
+        let a_free_or_static = self.free_regions.is_free_or_static(a);
+        if a_free_or_static && b_free_or_static {
+            return sub_free_regions(a, b);
+        }
+
+        // For other cases, leverage the LUB code to find the LUB and
+        // check if it is equal to `b`.
+        self.lub_concrete_regions(a, b) == b
+    }
+
+    /// Returns the LUB of the concrete regions `a` and `b` if it is
+    /// exactly one of the two (i.e., if one already contains the
+    /// other), and `None` otherwise. Unlike `lub_regions`, this never
+    /// creates a combination variable.
+    pub fn lub_regions_exact(&self, a: Region<'tcx>, b: Region<'tcx>) -> Option<Region<'tcx>> {
+        let lub = self.lub_concrete_regions(a, b);
+        if lub == a || lub == b { Some(lub) } else { None }
+    }
+
+    /// The GLB analog of `lub_regions_exact`.
+    pub fn glb_regions_exact(&self, a: Region<'tcx>, b: Region<'tcx>) -> Option<Region<'tcx>> {
+        match self.try_glb_concrete(a, b) {
+            Ok(glb) if glb == a || glb == b => Some(glb),
+            _ => None,
+        }
+    }
+
+    /// Computes the greatest-lower-bound of two concrete regions; i.e.,
+    /// the largest region `c` such that `c <= a` and `c <= b`.
+    ///
+    /// Neither `a` nor `b` may be an inference variable. If the only
+    /// region known to be contained in both is some empty region, then
+    /// `a` and `b` are considered not to overlap, and an error is
+    /// returned instead.
+    pub fn try_glb_concrete(
+        &self,
+        a: Region<'tcx>,
+        b: Region<'tcx>,
+    ) -> RelateResult<'tcx, Region<'tcx>> {
+        let r = match (a, b) {
+            (&ty::ReLateBound(..), _)
+            | (_, &ty::ReLateBound(..))
+            | (&ty::ReErased, _)
+            | (_, &ty::ReErased)
+            | (&ty::ReVar(_), _)
+            | (_, &ty::ReVar(_)) => {
+                bug!("cannot relate region: GLB({:?}, {:?})", a, b);
+            }
+
+            (&ty::ReStatic, r) | (r, &ty::ReStatic) => {
+                // everything is contained in `'static`, including the
+                // empty regions, so this arm also covers `('static, empty)`
+                r
+            }
+
+            (&ty::ReEmpty(a_ui), &ty::ReEmpty(b_ui)) => {
+                // Empty regions are ordered according to the universe
+                // they are associated with; the GLB is the one from the
+                // larger universe.
+                self.tcx.mk_region(ty::ReEmpty(a_ui.max(b_ui)))
+            }
+
+            (&ty::ReEmpty(empty_ui), &ty::RePlaceholder(placeholder))
+            | (&ty::RePlaceholder(placeholder), &ty::ReEmpty(empty_ui)) => {
+                // The empty region of a universe that can name the
+                // placeholder is contained in it.
+                self.tcx.mk_region(ty::ReEmpty(empty_ui.max(placeholder.universe)))
+            }
+
+            (r @ &ty::ReEmpty(_), &ty::ReEarlyBound(_) | &ty::ReFree(_))
+            | (&ty::ReEarlyBound(_) | &ty::ReFree(_), r @ &ty::ReEmpty(_)) => {
+                // All empty regions are less than early-bound and free
+                // regions. These are spelled out, rather than matching
+                // `(ReEmpty, _)`, so that the result for each pairing does
+                // not depend on the order of the arms above.
+                r
+            }
+
+            (&ty::ReEarlyBound(_) | &ty::ReFree(_), &ty::ReEarlyBound(_) | &ty::ReFree(_)) => {
+                // Unless one contains the other, we know of no region that
+                // is contained in both. A region that contains both (like
+                // their LUB) would be an upper bound, and using it as the
+                // GLB would claim that values valid for it are valid for
+                // `a` and `b`, which is unsound.
+                //
+                // An empty region is contained in both, so it would be a
+                // sound answer, but a useless one: callers such as
+                // `tightest_upper_bound` want to know that the two are
+                // unrelated, which an error tells them and `ReEmpty` would
+                // hide. (Unlike the old scope regions, free regions are
+                // never known to be disjoint, only not known to overlap.)
+                //
+                // If each is declared to outlive the other, either will do,
+                // but pick the same one whichever order they come in.
+                let a_sub_b = self.free_regions.sub_free_regions(self.tcx, a, b);
+                let b_sub_a = self.free_regions.sub_free_regions(self.tcx, b, a);
+                match (a_sub_b, b_sub_a) {
+                    (true, true) => a.min(b),
+                    (true, false) => a,
+                    (false, true) => b,
+                    (false, false) => return Err(TypeError::RegionsNoOverlap(a, b)),
+                }
+            }
+
+            // For these types, we cannot define any additional
+            // relationship:
+            (&ty::RePlaceholder(..), _) | (_, &ty::RePlaceholder(..)) => {
+                if a == b {
+                    a
+                } else {
+                    return Err(TypeError::RegionsNoOverlap(a, b));
+                }
+            }
+        };
+
+        debug!("try_glb_concrete({:?}, {:?}) = {:?}", a, b, r);
+
+        Ok(r)
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -88,7 +352,25 @@ pub fn sub_free_regions(
 
     /// Check whether `r_a <= r_b` is found in the relation.
     fn check_relation(&self, r_a: Region<'tcx>, r_b: Region<'tcx>) -> bool {
-        r_a == r_b || self.relation.contains(&r_a, &r_b)
+        self.same_free_region(r_a, r_b) || self.relation.contains(&r_a, &r_b)
+    }
+
+    /// True if `r_a` and `r_b` denote the same lifetime. Usually that just
+    /// means they are the same region, but a lifetime parameter can be
+    /// reached both as `ReEarlyBound` and as a named `ReFree`, and the
+    /// relation never records that those outlive one another.
+    fn same_free_region(&self, r_a: Region<'tcx>, r_b: Region<'tcx>) -> bool {
+        fn param_def_id(r: Region<'_>) -> Option<DefId> {
+            match *r {
+                ty::ReEarlyBound(ebr) => Some(ebr.def_id),
+                ty::ReFree(ty::FreeRegion { bound_region: ty::BrNamed(def_id, _), .. }) => {
+                    Some(def_id)
+                }
+                _ => None,
+            }
+        }
+
+        r_a == r_b || matches!((param_def_id(r_a), param_def_id(r_b)), (Some(a), Some(b)) if a == b)
     }
 
     /// True for free regions other than `'static`.
@@ -109,6 +391,9 @@ pub fn is_free_or_static(&self, r: Region<'_>) -> bool {
     /// cases, this is more conservative than necessary, in order to
     /// avoid making arbitrary choices. See
     /// `TransitiveRelation::postdom_upper_bound` for more details.
+    ///
+    /// `relation` is queried through its transitive closure, so chains of
+    /// outlives relations are followed.
     pub fn lub_free_regions(
         &self,
         tcx: TyCtxt<'tcx>,
@@ -118,9 +403,12 @@ pub fn lub_free_regions(
         debug!("lub_free_regions(r_a={:?}, r_b={:?})", r_a, r_b);
         assert!(self.is_free(r_a));
         assert!(self.is_free(r_b));
-        let result = if r_a == r_b {
-            r_a
+        let result = if self.same_free_region(r_a, r_b) {
+            // Either will do, but pick the same one whatever the order of
+            // the arguments, so that the LUB stays commutative.
+            cmp::min(r_a, r_b)
         } else {
+            // `postdom_upper_bound` already handles one containing the other.
             match self.relation.postdom_upper_bound(&r_a, &r_b) {
                 None => tcx.lifetimes.re_static,
                 Some(r) => *r,
@@ -68,12 +68,7 @@ fn regions(
         debug!("{}.regions({:?}, {:?})", self.tag(), a, b);
 
         let origin = Subtype(box self.fields.trace.clone());
-        Ok(self.fields.infcx.inner.borrow_mut().unwrap_region_constraints().glb_regions(
-            self.tcx(),
-            origin,
-            a,
-            b,
-        ))
+        Ok(self.fields.infcx.glb_regions(origin, a, b))
     }
 
     fn consts(
@@ -97,6 +92,12 @@ fn binders<T>(
         // When higher-ranked types are involved, computing the LUB is
         // very challenging, switch to invariance. This is obviously
         // overly conservative but works ok in practice.
+        //
+        // (As for the LUB, there used to be a generalization step here
+        // that mapped each region of the result back to a bound region
+        // based on its "tainted" set, creating fresh bound regions where
+        // no single region from each side fit. It was removed along with
+        // those sets; see `Lub::binders`.)
         self.relate_with_variance(ty::Variance::Invariant, ty::VarianceDiagInfo::default(), a, b)?;
         Ok(a)
     }
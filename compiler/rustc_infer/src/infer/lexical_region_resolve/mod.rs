@@ -1,47 +1,124 @@
 //! Lexical region resolution.
 
+use crate::infer::region_constraints::CombineMapType;
 use crate::infer::region_constraints::Constraint;
 use crate::infer::region_constraints::GenericKind;
 use crate::infer::region_constraints::MemberConstraint;
 use crate::infer::region_constraints::RegionConstraintData;
+use crate::infer::region_constraints::RegionGraphStats;
 use crate::infer::region_constraints::VarInfos;
 use crate::infer::region_constraints::VerifyBound;
+use crate::infer::free_regions::FreeRegionMap;
+use crate::infer::free_regions::RegionLattice;
+use crate::infer::RegionConstraintCategory;
 use crate::infer::RegionRelations;
 use crate::infer::RegionVariableOrigin;
 use crate::infer::RegionckMode;
 use crate::infer::SubregionOrigin;
-use rustc_data_structures::fx::FxHashSet;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 use rustc_data_structures::graph::implementation::{
     Direction, Graph, NodeIndex, INCOMING, OUTGOING,
 };
+use rustc_data_structures::sync::Lrc;
+use rustc_hir::def_id::DefId;
 use rustc_index::vec::{Idx, IndexVec};
 use rustc_middle::ty::fold::TypeFoldable;
 use rustc_middle::ty::{self, Ty, TyCtxt};
-use rustc_middle::ty::{ReEarlyBound, ReEmpty, ReErased, ReFree, ReStatic};
-use rustc_middle::ty::{ReLateBound, RePlaceholder, ReVar};
+use rustc_middle::ty::{ReEarlyBound, ReEmpty, ReFree, ReStatic};
+use rustc_middle::ty::{ReLateBound, ReVar};
 use rustc_middle::ty::{Region, RegionVid};
 use rustc_span::Span;
+use std::cell::{Cell, RefCell};
 use std::fmt;
+use std::mem;
+use std::ops::ControlFlow;
+
+#[cfg(test)]
+mod tests;
 
 /// This function performs lexical region resolution given a complete
 /// set of constraints and variable origins. It performs a fixed-point
 /// iteration to find region values which satisfy all constraints,
 /// assuming such values can be found. It returns the final values of
 /// all the variables as well as a set of errors that must be reported.
+///
+/// If `budget` is given, resolution gives up after that many steps and
+/// reports a `RegionResolutionError::Timeout` instead.
 pub fn resolve<'tcx>(
     region_rels: &RegionRelations<'_, 'tcx>,
     var_infos: VarInfos,
     data: RegionConstraintData<'tcx>,
     mode: RegionckMode,
+    unbounded: UnboundedRegionValue,
+    budget: Option<usize>,
 ) -> (LexicalRegionResolutions<'tcx>, Vec<RegionResolutionError<'tcx>>) {
-    debug!("RegionConstraintData: resolve_regions()");
     let mut errors = vec![];
-    let mut resolver = LexicalResolver { region_rels, var_infos, data };
+    let values =
+        resolve_with(region_rels, var_infos, data, mode, unbounded, budget, &mut |error| {
+            errors.push(error);
+            ControlFlow::CONTINUE
+        });
+    (values, errors)
+}
+
+/// Like `resolve`, but rather than collecting the errors, passes each one
+/// to `on_error` as soon as it is found. If `on_error` returns
+/// `ControlFlow::Break`, no further errors are looked for. The values
+/// returned are the same either way.
+pub fn resolve_with<'tcx>(
+    region_rels: &RegionRelations<'_, 'tcx>,
+    var_infos: VarInfos,
+    data: RegionConstraintData<'tcx>,
+    mode: RegionckMode,
+    unbounded: UnboundedRegionValue,
+    budget: Option<usize>,
+    on_error: &mut dyn FnMut(RegionResolutionError<'tcx>) -> ControlFlow<()>,
+) -> LexicalRegionResolutions<'tcx> {
+    resolve_in_lattice(
+        region_rels,
+        region_rels,
+        var_infos,
+        data,
+        mode,
+        unbounded,
+        budget,
+        on_error,
+        None,
+    )
+}
+
+/// Like `resolve_with`, but compares concrete regions using `lattice`
+/// rather than the usual rules implemented by `region_rels`. This is
+/// meant for experimenting with new kinds of regions.
+///
+/// If `on_event` is given, it is told about each phase of the resolution
+/// and every change made to the value of a variable, which is enough to
+/// replay how the values evolved.
+pub fn resolve_in_lattice<'tcx>(
+    region_rels: &RegionRelations<'_, 'tcx>,
+    lattice: &dyn RegionLattice<'tcx>,
+    var_infos: VarInfos,
+    data: RegionConstraintData<'tcx>,
+    mode: RegionckMode,
+    unbounded: UnboundedRegionValue,
+    budget: Option<usize>,
+    on_error: &mut dyn FnMut(RegionResolutionError<'tcx>) -> ControlFlow<()>,
+    on_event: Option<&mut dyn FnMut(ResolutionEvent<'tcx>)>,
+) -> LexicalRegionResolutions<'tcx> {
+    debug!("RegionConstraintData: resolve_regions()");
+    let mut errors = ErrorSink { on_error, stopped: false };
+    let mut resolver = LexicalResolver {
+        region_rels,
+        lattice,
+        var_infos,
+        data,
+        unbounded,
+        budget: StepBudget::new(budget),
+        sub_cache: Default::default(),
+        on_event: on_event.map(RefCell::new),
+    };
     match mode {
-        RegionckMode::Solve => {
-            let values = resolver.infer_variable_values(&mut errors);
-            (values, errors)
-        }
+        RegionckMode::Solve => resolver.infer_variable_values(&mut errors),
         RegionckMode::Erase { suppress_errors: false } => {
             // Do real inference to get errors, then erase the results.
             let mut values = resolver.infer_variable_values(&mut errors);
@@ -51,11 +128,77 @@ pub fn resolve<'tcx>(
                 VarValue::Value(ref mut r) => *r = re_erased,
                 VarValue::ErrorValue => {}
             });
-            (values, errors)
+            values
         }
         RegionckMode::Erase { suppress_errors: true } => {
             // Skip region inference entirely.
-            (resolver.erased_data(region_rels.tcx), Vec::new())
+            resolver.erased_data(region_rels.tcx)
+        }
+    }
+}
+
+/// The value given to region variables that have no upper bound at all,
+/// i.e., that no constraint requires to be contained in anything.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnboundedRegionValue {
+    /// The smallest value allowed by the variable's lower bounds (the
+    /// empty region, if there are none).
+    Smallest,
+
+    /// `'static`.
+    Static,
+}
+
+impl Default for UnboundedRegionValue {
+    fn default() -> Self {
+        UnboundedRegionValue::Smallest
+    }
+}
+
+/// The phases of lexical region resolution reported to the `on_event`
+/// callback of `resolve_in_lattice`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResolutionPhase {
+    /// Growing variables to cover their lower bounds.
+    Expansion,
+
+    /// Checking variables against their upper bounds, and marking those
+    /// that exceed one as errors.
+    Contraction,
+}
+
+/// A structured event from lexical region resolution; see
+/// `resolve_in_lattice`. As with `TraceStep`, a value of `None` means
+/// that no value could be inferred for the variable.
+#[derive(Copy, Clone, Debug)]
+pub enum ResolutionEvent<'tcx> {
+    PhaseStart(ResolutionPhase),
+    NodeUpdated {
+        vid: RegionVid,
+        from: Option<Region<'tcx>>,
+        to: Option<Region<'tcx>>,
+    },
+
+    /// `iterations` is the number of steps the phase took to reach a
+    /// fixed point, or 0 for phases that are not iterative.
+    PhaseEnd {
+        phase: ResolutionPhase,
+        iterations: usize,
+    },
+}
+
+/// Receives the errors found during resolution. Once the handler asks to
+/// stop, further errors are dropped, and the error collection code uses
+/// `stopped` to skip work that would only find more of them.
+struct ErrorSink<'a, 'tcx> {
+    on_error: &'a mut dyn FnMut(RegionResolutionError<'tcx>) -> ControlFlow<()>,
+    stopped: bool,
+}
+
+impl<'tcx> ErrorSink<'_, 'tcx> {
+    fn push(&mut self, error: RegionResolutionError<'tcx>) {
+        if !self.stopped {
+            self.stopped = (self.on_error)(error).is_break();
         }
     }
 }
@@ -65,6 +208,69 @@ pub fn resolve<'tcx>(
 pub struct LexicalRegionResolutions<'tcx> {
     values: IndexVec<RegionVid, VarValue<'tcx>>,
     error_region: ty::Region<'tcx>,
+
+    /// With `-Z trace-region-values`, every change made to each
+    /// variable's value during resolution, in order.
+    trace: Option<IndexVec<RegionVid, Vec<TraceStep<'tcx>>>>,
+
+    /// With `-Z dump-region-frames`, the constraint graph in DOT format,
+    /// annotated with the value of each variable as it was after each
+    /// pass of expansion, in order.
+    frames: Option<Vec<String>>,
+
+    /// The variables `prune_to_roots` dropped. They keep the empty region
+    /// of their universe, whatever constrained them.
+    pruned: FxHashSet<RegionVid>,
+
+    /// The variables with no lower bound in the constraint graph; see
+    /// `note_unconstrained_vars`.
+    unconstrained: FxHashSet<RegionVid>,
+
+    /// How many iterations expansion took to reach a fixed point.
+    expansion_iterations: usize,
+
+    /// The size of the problem solved, if it was solved rather than
+    /// erased; see `graph_stats`.
+    graph_stats: Option<RegionGraphStats>,
+}
+
+/// How resolution went for a single region variable; see
+/// `LexicalRegionResolutions::var_statuses`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VarStatus<'tcx> {
+    /// The variable was inferred to be the given region.
+    Resolved(Region<'tcx>),
+
+    /// Nothing was required to be contained in the variable, so it was
+    /// left as the empty region of its universe (or, with no upper bound
+    /// either, grown as `UnboundedRegionValue` says).
+    Unconstrained,
+
+    /// The variable was dropped by `prune_to_roots`, so no value was
+    /// inferred for it, and it was left as the empty region of its
+    /// universe.
+    Pruned,
+
+    /// No value could be inferred for the variable; an error was reported
+    /// for it or for a variable it depends on.
+    Errored,
+}
+
+/// One change to the value of a region variable during lexical region
+/// resolution, as recorded with `-Z trace-region-values`. A value of
+/// `None` means that no value could be inferred for the variable.
+#[derive(Copy, Clone, Debug)]
+pub struct TraceStep<'tcx> {
+    pub vid: RegionVid,
+    pub old_value: Option<Region<'tcx>>,
+    pub new_value: Option<Region<'tcx>>,
+
+    /// The constraint that forced the change, if it was a single one.
+    /// This is `None` for changes made to satisfy a member constraint,
+    /// for variables that became errors because a variable they depend
+    /// on did, and for variables with no upper bound that were set to
+    /// `'static` (see `UnboundedRegionValue`).
+    pub cause: Option<Constraint<'tcx>>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -73,6 +279,15 @@ enum VarValue<'tcx> {
     ErrorValue,
 }
 
+impl<'tcx> VarValue<'tcx> {
+    fn as_region(self) -> Option<Region<'tcx>> {
+        match self {
+            VarValue::Value(r) => Some(r),
+            VarValue::ErrorValue => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum RegionResolutionError<'tcx> {
     /// `ConcreteFailure(o, a, b)`:
@@ -86,19 +301,26 @@ pub enum RegionResolutionError<'tcx> {
     /// `a` (but none of the known bounds are sufficient).
     GenericBoundFailure(SubregionOrigin<'tcx>, GenericKind<'tcx>, Region<'tcx>),
 
-    /// `SubSupConflict(v, v_origin, sub_origin, sub_r, sup_origin, sup_r)`:
+    /// Could not infer a value for `var` (which has origin `var_origin`)
+    /// because `sub_region <= var` (due to `sub_origin`) but
+    /// `var <= sup_region` (due to `sup_origin`) and
+    /// `sub_region <= sup_region` does not hold. `spans` are the spans of
+    /// the origins of all of `var`'s concrete bounds, not just these two,
+    /// for reporting every place that contributed to the conflict; all the
+    /// conflicts of one variable share them.
     ///
-    /// Could not infer a value for `v` (which has origin `v_origin`)
-    /// because `sub_r <= v` (due to `sub_origin`) but `v <= sup_r` (due to `sup_origin`) and
-    /// `sub_r <= sup_r` does not hold.
-    SubSupConflict(
-        RegionVid,
-        RegionVariableOrigin,
-        SubregionOrigin<'tcx>,
-        Region<'tcx>,
-        SubregionOrigin<'tcx>,
-        Region<'tcx>,
-    ),
+    /// There are no scope regions here, so this never stands for a borrow
+    /// escaping its scope ("does not live long enough"); such errors come
+    /// from the NLL borrow checker, which reports them itself.
+    SubSupConflict {
+        var: RegionVid,
+        var_origin: RegionVariableOrigin,
+        sub_origin: SubregionOrigin<'tcx>,
+        sub_region: Region<'tcx>,
+        sup_origin: SubregionOrigin<'tcx>,
+        sup_region: Region<'tcx>,
+        spans: Lrc<[Span]>,
+    },
 
     /// Indicates a `'b: 'a` constraint where `'a` is in a universe that
     /// cannot name the placeholder `'b`.
@@ -114,6 +336,98 @@ pub enum RegionResolutionError<'tcx> {
     /// impl trait processing explicitly -- basically, the impl trait's hidden type
     /// included some region that it was not supposed to.
     MemberConstraintFailure { span: Span, hidden_ty: Ty<'tcx>, member_region: Region<'tcx> },
+
+    /// `PinViolation(o, v, expected, actual)`:
+    ///
+    /// `o` pinned `v` to `expected` (see `pin_region_var`), but its lower
+    /// bounds made it grow to `actual` instead.
+    PinViolation(SubregionOrigin<'tcx>, RegionVid, Region<'tcx>, Region<'tcx>),
+
+    /// `StrictnessViolation(o, a, b)`:
+    ///
+    /// `o` requires that `a` be strictly contained in `b` (see
+    /// `make_strict_subregion`), but both were inferred to be the same
+    /// region, given here.
+    StrictnessViolation(SubregionOrigin<'tcx>, Region<'tcx>, Region<'tcx>),
+
+    /// Resolution was given a budget (see `resolve`) and ran out of it
+    /// after `iterations` steps, over `node_count` region variables. The
+    /// values of all variables are then unknown, and no other errors are
+    /// reported.
+    Timeout { iterations: usize, node_count: usize },
+}
+
+/// A coarse classification of `RegionResolutionError`s, for callers that
+/// only care about what kind of error occurred. (`RegionResolutionError`
+/// itself cannot be compared for equality, because the origins it carries
+/// cannot.)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegionResolutionErrorKind {
+    ConcreteFailure,
+    GenericBoundFailure,
+    SubSupConflict,
+    UpperBoundUniverseConflict,
+    MemberConstraintFailure,
+    PinViolation,
+    StrictnessViolation,
+    Timeout,
+}
+
+impl<'tcx> RegionResolutionError<'tcx> {
+    pub fn kind(&self) -> RegionResolutionErrorKind {
+        match *self {
+            RegionResolutionError::ConcreteFailure(..) => {
+                RegionResolutionErrorKind::ConcreteFailure
+            }
+            RegionResolutionError::GenericBoundFailure(..) => {
+                RegionResolutionErrorKind::GenericBoundFailure
+            }
+            RegionResolutionError::SubSupConflict { .. } => {
+                RegionResolutionErrorKind::SubSupConflict
+            }
+            RegionResolutionError::UpperBoundUniverseConflict(..) => {
+                RegionResolutionErrorKind::UpperBoundUniverseConflict
+            }
+            RegionResolutionError::MemberConstraintFailure { .. } => {
+                RegionResolutionErrorKind::MemberConstraintFailure
+            }
+            RegionResolutionError::PinViolation(..) => RegionResolutionErrorKind::PinViolation,
+            RegionResolutionError::StrictnessViolation(..) => {
+                RegionResolutionErrorKind::StrictnessViolation
+            }
+            RegionResolutionError::Timeout { .. } => RegionResolutionErrorKind::Timeout,
+        }
+    }
+
+    /// The category of the constraint this error is about, so that related
+    /// errors can be grouped. For a `SubSupConflict`, this is the category
+    /// of the upper bound that the lower bound does not fit in. Errors that
+    /// do not come from a single constraint are `Unknown`.
+    pub fn category(&self) -> RegionConstraintCategory {
+        match *self {
+            RegionResolutionError::ConcreteFailure(ref origin, ..)
+            | RegionResolutionError::GenericBoundFailure(ref origin, ..)
+            | RegionResolutionError::SubSupConflict { sup_origin: ref origin, .. }
+            | RegionResolutionError::UpperBoundUniverseConflict(_, _, _, ref origin, _)
+            | RegionResolutionError::PinViolation(ref origin, ..)
+            | RegionResolutionError::StrictnessViolation(ref origin, ..) => origin.category(),
+            RegionResolutionError::MemberConstraintFailure { .. }
+            | RegionResolutionError::Timeout { .. } => RegionConstraintCategory::Unknown,
+        }
+    }
+
+    /// For a `ConcreteFailure`, the constraint that does not hold. Several
+    /// constraints can share an origin (and so a span); this tells which
+    /// of them failed. For constraints that were only verified, the
+    /// regions are the values of the variables they mentioned.
+    pub fn failed_constraint(&self) -> Option<Constraint<'tcx>> {
+        match *self {
+            RegionResolutionError::ConcreteFailure(_, sub, sup) => {
+                Some(Constraint::RegSubReg(sub, sup))
+            }
+            _ => None,
+        }
+    }
 }
 
 struct RegionAndOrigin<'tcx> {
@@ -121,12 +435,298 @@ struct RegionAndOrigin<'tcx> {
     origin: SubregionOrigin<'tcx>,
 }
 
-type RegionGraph<'tcx> = Graph<(), Constraint<'tcx>>;
+/// The spans of the origins of `lower` and `upper`, in order and each
+/// once, for `SubSupConflict`.
+fn bound_spans<'tcx>(
+    lower: &[RegionAndOrigin<'tcx>],
+    upper: &[RegionAndOrigin<'tcx>],
+) -> Lrc<[Span]> {
+    let mut spans: Vec<Span> = lower.iter().chain(upper).map(|bound| bound.origin.span()).collect();
+    spans.sort();
+    spans.dedup();
+    spans.into()
+}
+
+/// Drops everything that mentions a variable not connected to one of
+/// `data.roots` through `VarSubVar` constraints, so that resolution
+/// neither computes values nor reports errors for them. The variables
+/// dropped are recorded in `var_data`.
+fn prune_to_roots<'tcx>(
+    data: &mut RegionConstraintData<'tcx>,
+    var_data: &mut LexicalRegionResolutions<'tcx>,
+) {
+    let mut edges: FxHashMap<RegionVid, Vec<RegionVid>> = FxHashMap::default();
+    for constraint in data.constraints.keys() {
+        if let Constraint::VarSubVar(a, b) = *constraint {
+            edges.entry(a).or_default().push(b);
+            edges.entry(b).or_default().push(a);
+        }
+    }
+    let mut kept = FxHashSet::default();
+    let mut stack = data.roots.clone();
+    while let Some(vid) = stack.pop() {
+        if kept.insert(vid) {
+            stack.extend(edges.get(&vid).into_iter().flatten().copied());
+        }
+    }
+    debug!("prune_to_roots: keeping {} of {} variables", kept.len(), var_data.values.len());
+    var_data.pruned = var_data.values.indices().filter(|vid| !kept.contains(vid)).collect();
+
+    let kept_region = |r: Region<'tcx>| match *r {
+        ReVar(vid) => kept.contains(&vid),
+        _ => true,
+    };
+    data.constraints.retain(|constraint, _| match *constraint {
+        Constraint::VarSubVar(a, _) | Constraint::VarSubReg(a, _) => kept.contains(&a),
+        Constraint::RegSubVar(_, b) => kept.contains(&b),
+        Constraint::RegSubReg(..) => true,
+    });
+    data.member_constraints.retain(|c| kept_region(c.member_region));
+    data.verifys.retain(|verify| kept_region(verify.region));
+    data.verify_subregions.retain(|&(sub, sup, _)| kept_region(sub) && kept_region(sup));
+    data.strict_subregions.retain(|&(sub, sup, _)| kept_region(sub) && kept_region(sup));
+    data.pins.retain(|&(vid, ..)| kept.contains(&vid));
+    data.givens.retain(|&(_, vid)| kept.contains(&vid));
+}
+
+/// The variables that a verify, a member constraint or a verify-only
+/// subregion constraint requires to be contained in something; none of
+/// these are part of the constraint graph. See `grow_unbounded_vars`.
+fn vars_bounded_outside_graph(data: &RegionConstraintData<'_>) -> FxHashSet<RegionVid> {
+    data.verifys
+        .iter()
+        .map(|verify| verify.region)
+        .chain(data.member_constraints.iter().map(|c| c.member_region))
+        .chain(data.verify_subregions.iter().map(|&(sub, ..)| sub))
+        .filter_map(|r| match *r {
+            ReVar(vid) => Some(vid),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The pairs of a lower and an upper bound of a variable in `universe`
+/// where the lower bound does not fit in the upper one, according to
+/// `is_sub`. A placeholder lower bound that `universe` cannot name counts
+/// as `re_static`. Only the first pair is returned, unless `all_conflicts`
+/// is set; then every pair that involves neither a lower nor an upper
+/// bound of a pair before it is.
+fn conflicting_bounds<'b, 'tcx>(
+    lower_bounds: &'b [RegionAndOrigin<'tcx>],
+    upper_bounds: &'b [RegionAndOrigin<'tcx>],
+    universe: ty::UniverseIndex,
+    re_static: Region<'tcx>,
+    all_conflicts: bool,
+    is_sub: impl Fn(Region<'tcx>, Region<'tcx>) -> bool,
+) -> Vec<(&'b RegionAndOrigin<'tcx>, &'b RegionAndOrigin<'tcx>)> {
+    let mut conflicts = vec![];
+    let mut reported_lower = FxHashSet::default();
+    let mut reported_upper = FxHashSet::default();
+    for lower_bound in lower_bounds {
+        let effective_lower_bound = match *lower_bound.region {
+            ty::RePlaceholder(p) if universe.cannot_name(p.universe) => re_static,
+            _ => lower_bound.region,
+        };
+        for upper_bound in upper_bounds {
+            if reported_lower.contains(&lower_bound.region)
+                || reported_upper.contains(&upper_bound.region)
+            {
+                continue;
+            }
+            if !is_sub(effective_lower_bound, upper_bound.region) {
+                conflicts.push((lower_bound, upper_bound));
+                if !all_conflicts {
+                    return conflicts;
+                }
+                reported_lower.insert(lower_bound.region);
+                reported_upper.insert(upper_bound.region);
+            }
+        }
+    }
+    conflicts
+}
+
+pub type RegionGraph<'tcx> = Graph<(), Constraint<'tcx>>;
+
+/// Builds the graph of the constraints in `data`, over `num_vars` region
+/// variables. Node `i`, for `i < num_vars`, stands for the `RegionVid` with
+/// index `i`. The two nodes after those stand for concrete regions: one is
+/// the source of all `RegSubVar` edges and the other the sink of all
+/// `VarSubReg` edges. (`RegSubReg` constraints have no edge.)
+pub fn construct_region_graph<'tcx>(
+    num_vars: usize,
+    data: &RegionConstraintData<'tcx>,
+) -> RegionGraph<'tcx> {
+    let mut graph = Graph::new();
+
+    for _ in 0..num_vars {
+        graph.add_node(());
+    }
+
+    // Issue #30438: two distinct dummy nodes, one for incoming
+    // edges (dummy_source) and another for outgoing edges
+    // (dummy_sink). In `dummy -> a -> b -> dummy`, using one
+    // dummy node leads one to think (erroneously) there exists a
+    // path from `b` to `a`. Two dummy nodes sidesteps the issue.
+    let dummy_source = graph.add_node(());
+    let dummy_sink = graph.add_node(());
+
+    for (constraint, origin) in &data.constraints {
+        // Bound regions should have been replaced with variables or
+        // placeholders long before we get here; if one made it into
+        // the constraint set, that is a compiler bug, not something
+        // the lattice operations below should try to smooth over.
+        match *constraint {
+            Constraint::RegSubVar(r, _) | Constraint::VarSubReg(_, r) => {
+                if let ReLateBound(..) = *r {
+                    span_bug!(origin.span(), "bound region {:?} in region resolution", r);
+                }
+            }
+            Constraint::RegSubReg(a, b) => {
+                for r in [a, b] {
+                    if let ReLateBound(..) = *r {
+                        span_bug!(origin.span(), "bound region {:?} in region resolution", r);
+                    }
+                }
+            }
+            Constraint::VarSubVar(..) => {}
+        }
+
+        match *constraint {
+            Constraint::VarSubVar(a_id, b_id) => {
+                graph.add_edge(
+                    NodeIndex(a_id.index() as usize),
+                    NodeIndex(b_id.index() as usize),
+                    *constraint,
+                );
+            }
+            Constraint::RegSubVar(_, b_id) => {
+                graph.add_edge(dummy_source, NodeIndex(b_id.index() as usize), *constraint);
+            }
+            Constraint::VarSubReg(a_id, _) => {
+                graph.add_edge(NodeIndex(a_id.index() as usize), dummy_sink, *constraint);
+            }
+            Constraint::RegSubReg(..) => {
+                // this would be an edge from `dummy_source` to
+                // `dummy_sink`; just ignore it. Nor is it worth feeding
+                // `a <= b` into the variables that `b` flows into: if it
+                // holds, `a` is already covered by any value that covers
+                // `b`, and if it does not, it is reported on its own in
+                // `collect_errors`.
+            }
+        }
+    }
+
+    graph
+}
+
+/// Returns the node that stands for `vid` in a graph built by
+/// `construct_region_graph`. A graph only covers the variables that
+/// existed when it was built, so one that has outlived a rollback (or the
+/// creation of new variables) must not be queried; this asserts instead
+/// of handing back one of the dummy nodes, or an index past the end.
+pub fn var_node(graph: &RegionGraph<'_>, vid: RegionVid) -> NodeIndex {
+    let num_vars = graph.len_nodes() - 2;
+    assert!(
+        vid.index() < num_vars,
+        "region graph queried for {:?}, but only built for {} variables",
+        vid,
+        num_vars
+    );
+    NodeIndex(vid.index())
+}
+
+/// The number of distinct values a variable can take during resolution.
+/// A value is either the empty region of some variable's universe, or was
+/// put there by a constraint, by a member constraint, or by taking the LUB
+/// of two values, which yields one of them, `'static`, or a region of
+/// `free_regions`.
+fn num_region_values<'tcx>(
+    var_infos: &VarInfos,
+    data: &RegionConstraintData<'tcx>,
+    free_regions: &FreeRegionMap<'tcx>,
+) -> usize {
+    let mut regions: FxHashSet<Region<'tcx>> = free_regions.elements().copied().collect();
+    for constraint in data.constraints.keys() {
+        match *constraint {
+            Constraint::RegSubVar(r, _) | Constraint::VarSubReg(_, r) => {
+                regions.insert(r);
+            }
+            Constraint::RegSubReg(a, b) => {
+                regions.insert(a);
+                regions.insert(b);
+            }
+            Constraint::VarSubVar(..) => {}
+        }
+    }
+    for member_constraint in &data.member_constraints {
+        regions.extend(member_constraint.choice_regions.iter().copied());
+    }
+    let universes: FxHashSet<_> = var_infos.iter().map(|info| info.universe).collect();
+    // One more for `'static`.
+    regions.len() + universes.len() + 1
+}
+
+/// Counts the steps resolution takes against the budget given to
+/// `resolve`, if any. Every phase is charged for its work, so that a
+/// budget bounds the whole resolution and not just expansion.
+struct StepBudget {
+    limit: Option<usize>,
+    steps: Cell<usize>,
+    exhausted: Cell<bool>,
+}
+
+impl StepBudget {
+    fn new(limit: Option<usize>) -> Self {
+        StepBudget { limit, steps: Cell::new(0), exhausted: Cell::new(false) }
+    }
+
+    /// Counts `n` more steps, returning false if that would take more
+    /// than the limit. In that case only the steps left are counted, and
+    /// the budget stays exhausted: every later charge fails too.
+    fn charge(&self, n: usize) -> bool {
+        if self.exhausted.get() {
+            return false;
+        }
+        let steps = self.steps.get();
+        match self.limit {
+            Some(limit) if n > limit - steps => {
+                self.steps.set(limit);
+                self.exhausted.set(true);
+                false
+            }
+            _ => {
+                self.steps.set(steps + n);
+                true
+            }
+        }
+    }
+
+    fn exhausted(&self) -> bool {
+        self.exhausted.get()
+    }
+
+    fn steps(&self) -> usize {
+        self.steps.get()
+    }
+}
 
 struct LexicalResolver<'cx, 'tcx> {
     region_rels: &'cx RegionRelations<'cx, 'tcx>,
+    lattice: &'cx dyn RegionLattice<'tcx>,
     var_infos: VarInfos,
     data: RegionConstraintData<'tcx>,
+    unbounded: UnboundedRegionValue,
+
+    budget: StepBudget,
+
+    /// Results of `sub_concrete_regions`. Error collection asks about the
+    /// same pairs over and over (e.g., every lower bound of a variable
+    /// against every upper bound), and the answer only depends on
+    /// `lattice`, so it never changes during a resolution.
+    sub_cache: RefCell<FxHashMap<(Region<'tcx>, Region<'tcx>), bool>>,
+
+    on_event: Option<RefCell<&'cx mut dyn FnMut(ResolutionEvent<'tcx>)>>,
 }
 
 impl<'cx, 'tcx> LexicalResolver<'cx, 'tcx> {
@@ -136,7 +736,7 @@ fn tcx(&self) -> TyCtxt<'tcx> {
 
     fn infer_variable_values(
         &mut self,
-        errors: &mut Vec<RegionResolutionError<'tcx>>,
+        errors: &mut ErrorSink<'_, 'tcx>,
     ) -> LexicalRegionResolutions<'tcx> {
         let mut var_data = self.construct_var_data(self.tcx());
 
@@ -148,23 +748,130 @@ fn infer_variable_values(
             self.dump_constraints(self.region_rels)
         );
 
+        if !self.data.roots.is_empty() {
+            prune_to_roots(&mut self.data, &mut var_data);
+        }
+
         let graph = self.construct_graph();
+        var_data.note_unconstrained_vars(&graph, &self.data);
         self.expand_givens(&graph);
+        let limit = self.member_iteration_limit();
+        let mut iteration = 0;
         loop {
             self.expansion(&mut var_data);
-            if !self.enforce_member_constraints(&graph, &mut var_data) {
+            if self.budget.exhausted() || !self.enforce_member_constraints(&graph, &mut var_data) {
                 break;
             }
+            iteration += 1;
+            check_iteration_limit("member constraints", iteration, limit, self.region_rels.context);
         }
-        self.collect_errors(&mut var_data, errors);
-        self.collect_var_errors(&var_data, &graph, errors);
+        if !self.budget.exhausted() && self.unbounded == UnboundedRegionValue::Static {
+            self.grow_unbounded_vars(&graph, &mut var_data);
+        }
+        // Error collection is charged up front, one step per constraint
+        // and per variable, so that running out of budget never leaves
+        // only some of the errors reported.
+        if self.budget.exhausted() || !self.budget.charge(self.error_collection_steps()) {
+            // The values are only partially computed, so don't let anyone
+            // rely on them, nor on errors derived from them.
+            for value in var_data.values.iter_mut() {
+                *value = VarValue::ErrorValue;
+            }
+            errors.push(RegionResolutionError::Timeout {
+                iterations: self.budget.steps(),
+                node_count: self.num_vars(),
+            });
+            return var_data;
+        }
+        let tainted = self.collect_errors(&graph, &mut var_data, errors);
+        self.collect_var_errors(&var_data, &graph, &tainted, errors);
         var_data
     }
 
+    /// Sets every variable with no upper bound to `'static`: that is, with
+    /// no outgoing edges in `graph`, and not required to be contained in
+    /// anything by a verify, a member constraint or a verify-only
+    /// subregion constraint, none of which are part of the graph. Nothing
+    /// depends on the value of such a variable, so this cannot make
+    /// another one grow. Pruned variables are left alone: their
+    /// constraints were dropped, not absent.
+    fn grow_unbounded_vars(
+        &self,
+        graph: &RegionGraph<'tcx>,
+        var_values: &mut LexicalRegionResolutions<'tcx>,
+    ) {
+        let bounded = vars_bounded_outside_graph(&self.data);
+        let re_static = self.tcx().lifetimes.re_static;
+        for vid in var_values.values.indices() {
+            if !self.budget.charge(1) {
+                return;
+            }
+            let node = var_node(graph, vid);
+            if bounded.contains(&vid)
+                || var_values.pruned.contains(&vid)
+                || graph.adjacent_edges(node, OUTGOING).next().is_some()
+            {
+                continue;
+            }
+
+            let old_value = *var_values.value(vid);
+            if let VarValue::Value(r) = old_value {
+                if *r != ReStatic {
+                    debug!("grow_unbounded_vars: {:?} from {:?} to 'static", vid, r);
+                    *var_values.value_mut(vid) = VarValue::Value(re_static);
+                    self.note_update(var_values, vid, old_value, None);
+                }
+            }
+        }
+    }
+
     fn num_vars(&self) -> usize {
         self.var_infos.len()
     }
 
+    /// An upper bound on the number of iterations of the worklist loop in
+    /// `expansion`. Each iteration handles a change to the value of some
+    /// variable, and values only ever grow, so there cannot be more
+    /// iterations than `num_region_values` for each variable.
+    /// `-Z region-iteration-limit` overrides it, so that tests can check
+    /// what happens when it is hit.
+    fn iteration_limit(&self) -> usize {
+        if let Some(limit) = self.tcx().sess.opts.debugging_opts.region_iteration_limit {
+            return limit;
+        }
+        self.num_vars().saturating_mul(self.num_region_values())
+    }
+
+    /// Like `iteration_limit`, but for the loop that enforces member
+    /// constraints in `infer_variable_values`: each of its iterations makes
+    /// at least one member region grow.
+    fn member_iteration_limit(&self) -> usize {
+        if let Some(limit) = self.tcx().sess.opts.debugging_opts.region_iteration_limit {
+            return limit;
+        }
+        let member_vars: FxHashSet<_> =
+            self.data.member_constraints.iter().map(|c| c.member_region).collect();
+        member_vars.len().saturating_mul(self.num_region_values())
+    }
+
+    fn num_region_values(&self) -> usize {
+        num_region_values(&self.var_infos, &self.data, self.region_rels.free_regions)
+    }
+
+    /// The number of steps `collect_errors` and `collect_var_errors` are
+    /// charged for. Unlike hitting `iteration_limit`, running out of
+    /// budget is not a bug: inference just gives up.
+    fn error_collection_steps(&self) -> usize {
+        let data = &self.data;
+        data.pins.len()
+            + data.constraints.len()
+            + data.verify_subregions.len()
+            + data.strict_subregions.len()
+            + data.member_constraints.len()
+            + data.verifys.len()
+            + self.num_vars()
+    }
+
     /// Initially, the value for all variables is set to `'empty`, the
     /// empty region. The `expansion` phase will grow this larger.
     fn construct_var_data(&self, tcx: TyCtxt<'tcx>) -> LexicalRegionResolutions<'tcx> {
@@ -178,6 +885,17 @@ fn construct_var_data(&self, tcx: TyCtxt<'tcx>) -> LexicalRegionResolutions<'tcx
                 },
                 self.num_vars(),
             ),
+            trace: tcx
+                .sess
+                .opts
+                .debugging_opts
+                .trace_region_values
+                .then(|| IndexVec::from_elem_n(Vec::new(), self.num_vars())),
+            frames: tcx.sess.opts.debugging_opts.dump_region_frames.then(Vec::new),
+            pruned: FxHashSet::default(),
+            unconstrained: FxHashSet::default(),
+            expansion_iterations: 0,
+            graph_stats: Some(self.data.graph_stats(self.num_vars())),
         }
     }
 
@@ -191,6 +909,12 @@ fn erased_data(&self, tcx: TyCtxt<'tcx>) -> LexicalRegionResolutions<'tcx> {
                 VarValue::Value(tcx.lifetimes.re_erased),
                 self.num_vars(),
             ),
+            trace: None,
+            frames: None,
+            pruned: FxHashSet::default(),
+            unconstrained: FxHashSet::default(),
+            expansion_iterations: 0,
+            graph_stats: None,
         }
     }
 
@@ -216,7 +940,7 @@ fn expand_givens(&mut self, graph: &RegionGraph<'_>) {
         for (r, vid) in seeds {
             // While all things transitively reachable in the graph
             // from the variable (`'0` in the example above).
-            let seed_index = NodeIndex(vid.index() as usize);
+            let seed_index = var_node(graph, vid);
             for succ_index in graph.depth_traverse(seed_index, OUTGOING) {
                 let succ_index = succ_index.0;
 
@@ -244,6 +968,9 @@ fn enforce_member_constraints(
         // want to stop at the first constraint that makes a change.
         let mut any_changed = false;
         for member_constraint in &self.data.member_constraints {
+            if !self.budget.charge(1) {
+                return false;
+            }
             any_changed |= self.enforce_member_constraint(graph, member_constraint, var_values);
         }
         any_changed
@@ -341,232 +1068,94 @@ fn enforce_member_constraint(
         );
         if lub != member_lower_bound {
             *var_values.value_mut(member_vid) = VarValue::Value(least_choice);
+            self.note_update(var_values, member_vid, VarValue::Value(member_lower_bound), None);
             true
         } else {
             false
         }
     }
 
+    /// Grows each variable until it contains all of its lower bounds; see
+    /// `Expansion`.
     fn expansion(&self, var_values: &mut LexicalRegionResolutions<'tcx>) {
-        let mut constraints = IndexVec::from_elem_n(Vec::new(), var_values.values.len());
-        let mut changes = Vec::new();
-        for constraint in self.data.constraints.keys() {
-            let (a_vid, a_region, b_vid, b_data) = match *constraint {
-                Constraint::RegSubVar(a_region, b_vid) => {
-                    let b_data = var_values.value_mut(b_vid);
-                    (None, a_region, b_vid, b_data)
-                }
-                Constraint::VarSubVar(a_vid, b_vid) => match *var_values.value(a_vid) {
-                    VarValue::ErrorValue => continue,
-                    VarValue::Value(a_region) => {
-                        let b_data = var_values.value_mut(b_vid);
-                        (Some(a_vid), a_region, b_vid, b_data)
-                    }
-                },
-                Constraint::RegSubReg(..) | Constraint::VarSubReg(..) => {
-                    // These constraints are checked after expansion
-                    // is done, in `collect_errors`.
-                    continue;
-                }
-            };
-            if self.expand_node(a_region, b_vid, b_data) {
-                changes.push(b_vid);
-            }
-            if let Some(a_vid) = a_vid {
-                match *b_data {
-                    VarValue::Value(ReStatic) | VarValue::ErrorValue => (),
-                    _ => {
-                        constraints[a_vid].push((a_vid, b_vid));
-                        constraints[b_vid].push((a_vid, b_vid));
-                    }
-                }
-            }
-        }
-
-        while let Some(vid) = changes.pop() {
-            constraints[vid].retain(|&(a_vid, b_vid)| {
-                let a_region = match *var_values.value(a_vid) {
-                    VarValue::ErrorValue => return false,
-                    VarValue::Value(a_region) => a_region,
-                };
-                let b_data = var_values.value_mut(b_vid);
-                if self.expand_node(a_region, b_vid, b_data) {
-                    changes.push(b_vid);
-                }
-                !matches!(b_data, VarValue::Value(ReStatic) | VarValue::ErrorValue)
-            });
-        }
+        let expansion = Expansion {
+            lattice: self.lattice,
+            var_infos: &self.var_infos,
+            data: &self.data,
+            budget: &self.budget,
+            re_static: self.tcx().lifetimes.re_static,
+            iteration_limit: self.iteration_limit(),
+            context: self.region_rels.context,
+            emit: &|event| self.emit(event),
+        };
+        expansion.run(var_values);
     }
 
-    fn expand_node(
+    /// Called after the value of `vid` has changed from `old_value`.
+    fn note_update(
         &self,
-        a_region: Region<'tcx>,
-        b_vid: RegionVid,
-        b_data: &mut VarValue<'tcx>,
-    ) -> bool {
-        debug!("expand_node({:?}, {:?} == {:?})", a_region, b_vid, b_data);
-
-        match *a_region {
-            // Check if this relationship is implied by a given.
-            ty::ReEarlyBound(_) | ty::ReFree(_) => {
-                if self.data.givens.contains(&(a_region, b_vid)) {
-                    debug!("given");
-                    return false;
-                }
-            }
-
-            _ => {}
-        }
-
-        match *b_data {
-            VarValue::Value(cur_region) => {
-                // This is a specialized version of the `lub_concrete_regions`
-                // check below for a common case, here purely as an
-                // optimization.
-                let b_universe = self.var_infos[b_vid].universe;
-                if let ReEmpty(a_universe) = a_region {
-                    if *a_universe == b_universe {
-                        return false;
-                    }
-                }
-
-                let mut lub = self.lub_concrete_regions(a_region, cur_region);
-                if lub == cur_region {
-                    return false;
-                }
-
-                // Watch out for `'b: !1` relationships, where the
-                // universe of `'b` can't name the placeholder `!1`. In
-                // that case, we have to grow `'b` to be `'static` for the
-                // relationship to hold. This is obviously a kind of sub-optimal
-                // choice -- in the future, when we incorporate a knowledge
-                // of the parameter environment, we might be able to find a
-                // tighter bound than `'static`.
-                //
-                // (This might e.g. arise from being asked to prove `for<'a> { 'b: 'a }`.)
-                if let ty::RePlaceholder(p) = lub {
-                    if b_universe.cannot_name(p.universe) {
-                        lub = self.tcx().lifetimes.re_static;
-                    }
-                }
-
-                debug!("Expanding value of {:?} from {:?} to {:?}", b_vid, cur_region, lub);
-
-                *b_data = VarValue::Value(lub);
-                true
-            }
+        var_values: &mut LexicalRegionResolutions<'tcx>,
+        vid: RegionVid,
+        old_value: VarValue<'tcx>,
+        cause: Option<Constraint<'tcx>>,
+    ) {
+        var_values.record_step(vid, old_value, cause);
+        self.emit(ResolutionEvent::NodeUpdated {
+            vid,
+            from: old_value.as_region(),
+            to: var_values.value(vid).as_region(),
+        });
+    }
 
-            VarValue::ErrorValue => false,
+    fn emit(&self, event: ResolutionEvent<'tcx>) {
+        if let Some(on_event) = &self.on_event {
+            (on_event.borrow_mut())(event);
         }
     }
 
     /// True if `a <= b`, but not defined over inference variables.
     fn sub_concrete_regions(&self, a: Region<'tcx>, b: Region<'tcx>) -> bool {
-        let tcx = self.tcx();
-        let sub_free_regions = |r1, r2| self.region_rels.free_regions.sub_free_regions(tcx, r1, r2);
-
-        // Check for the case where we know that `'b: 'static` -- in that case,
-        // `a <= b` for all `a`.
-        let b_free_or_static = self.region_rels.free_regions.is_free_or_static(b);
-        if b_free_or_static && sub_free_regions(tcx.lifetimes.re_static, b) {
-            return true;
-        }
-
-        // If both `a` and `b` are free, consult the declared
-        // relationships.  Note that this can be more precise than the
-        // `lub` relationship defined below, since sometimes the "lub"
-        // is actually the `postdom_upper_bound` (see
-        // `TransitiveRelation` for more details).
-        let a_free_or_static = self.region_rels.free_regions.is_free_or_static(a);
-        if a_free_or_static && b_free_or_static {
-            return sub_free_regions(a, b);
+        if let Some(&result) = self.sub_cache.borrow().get(&(a, b)) {
+            return result;
         }
-
-        // For other cases, leverage the LUB code to find the LUB and
-        // check if it is equal to `b`.
-        self.lub_concrete_regions(a, b) == b
+        let result = self.lattice.sub_concrete_regions(a, b);
+        self.sub_cache.borrow_mut().insert((a, b), result);
+        result
     }
 
-    /// Returns the least-upper-bound of `a` and `b`; i.e., the
-    /// smallest region `c` such that `a <= c` and `b <= c`.
-    ///
-    /// Neither `a` nor `b` may be an inference variable (hence the
-    /// term "concrete regions").
     fn lub_concrete_regions(&self, a: Region<'tcx>, b: Region<'tcx>) -> Region<'tcx> {
-        let r = match (a, b) {
-            (&ReLateBound(..), _) | (_, &ReLateBound(..)) | (&ReErased, _) | (_, &ReErased) => {
-                bug!("cannot relate region: LUB({:?}, {:?})", a, b);
-            }
-
-            (&ReVar(v_id), _) | (_, &ReVar(v_id)) => {
-                span_bug!(
-                    self.var_infos[v_id].origin.span(),
-                    "lub_concrete_regions invoked with non-concrete \
-                     regions: {:?}, {:?}",
-                    a,
-                    b
-                );
-            }
-
-            (&ReStatic, _) | (_, &ReStatic) => {
-                // nothing lives longer than `'static`
-                self.tcx().lifetimes.re_static
-            }
-
-            (&ReEmpty(_), r @ (ReEarlyBound(_) | ReFree(_)))
-            | (r @ (ReEarlyBound(_) | ReFree(_)), &ReEmpty(_)) => {
-                // All empty regions are less than early-bound, free,
-                // and scope regions.
-                r
-            }
-
-            (&ReEmpty(a_ui), &ReEmpty(b_ui)) => {
-                // Empty regions are ordered according to the universe
-                // they are associated with.
-                let ui = a_ui.min(b_ui);
-                self.tcx().mk_region(ReEmpty(ui))
-            }
-
-            (&ReEmpty(empty_ui), &RePlaceholder(placeholder))
-            | (&RePlaceholder(placeholder), &ReEmpty(empty_ui)) => {
-                // If this empty region is from a universe that can
-                // name the placeholder, then the placeholder is
-                // larger; otherwise, the only ancestor is `'static`.
-                if empty_ui.can_name(placeholder.universe) {
-                    self.tcx().mk_region(RePlaceholder(placeholder))
-                } else {
-                    self.tcx().lifetimes.re_static
-                }
-            }
-
-            (&ReEarlyBound(_) | &ReFree(_), &ReEarlyBound(_) | &ReFree(_)) => {
-                self.region_rels.lub_free_regions(a, b)
-            }
-
-            // For these types, we cannot define any additional
-            // relationship:
-            (&RePlaceholder(..), _) | (_, &RePlaceholder(..)) => {
-                if a == b {
-                    a
-                } else {
-                    self.tcx().lifetimes.re_static
-                }
-            }
-        };
-
-        debug!("lub_concrete_regions({:?}, {:?}) = {:?}", a, b, r);
-
-        r
+        lub_concrete_regions(self.lattice, &self.var_infos, a, b)
     }
 
     /// After expansion is complete, go and check upper bounds (i.e.,
     /// cases where the region cannot grow larger than a fixed point)
-    /// and check that they are satisfied.
+    /// and check that they are satisfied. Returns the variables that
+    /// were set to `ErrorValue` but whose error is reported elsewhere:
+    /// those that only depend on a variable that failed its own check
+    /// (see `propagate_error_values`), and pinned variables.
     fn collect_errors(
         &self,
+        graph: &RegionGraph<'tcx>,
         var_data: &mut LexicalRegionResolutions<'tcx>,
-        errors: &mut Vec<RegionResolutionError<'tcx>>,
-    ) {
+        errors: &mut ErrorSink<'_, 'tcx>,
+    ) -> FxHashSet<RegionVid> {
+        self.emit(ResolutionEvent::PhaseStart(ResolutionPhase::Contraction));
+
+        // A pinned variable that grew past its region gets a dedicated
+        // error. Making it an error value keeps the upper bound check
+        // below from reporting it again as a `SubSupConflict`.
+        let mut pin_violations = vec![];
+        for &(vid, expected, ref origin) in &self.data.pins {
+            let actual = match *var_data.value(vid) {
+                VarValue::Value(actual) if actual != expected => actual,
+                _ => continue,
+            };
+            errors.push(RegionResolutionError::PinViolation(origin.clone(), vid, expected, actual));
+            *var_data.value_mut(vid) = VarValue::ErrorValue;
+            self.note_update(var_data, vid, VarValue::Value(actual), None);
+            pin_violations.push(vid);
+        }
+
         for (constraint, origin) in &self.data.constraints {
             debug!("collect_errors: constraint={:?} origin={:?}", constraint, origin);
             match *constraint {
@@ -575,7 +1164,7 @@ fn collect_errors(
                 }
 
                 Constraint::RegSubReg(sub, sup) => {
-                    if self.sub_concrete_regions(sub, sup) {
+                    if errors.stopped || self.sub_concrete_regions(sub, sup) {
                         continue;
                     }
 
@@ -611,13 +1200,69 @@ fn collect_errors(
                             origin, a_vid, a_region, b_region
                         );
                         *a_data = VarValue::ErrorValue;
+                        let old_value = VarValue::Value(a_region);
+                        self.note_update(var_data, a_vid, old_value, Some(*constraint));
                     }
                 }
             }
         }
 
+        let mut tainted = self.propagate_error_values(graph, var_data);
+        tainted.extend(pin_violations);
+        self.emit(ResolutionEvent::PhaseEnd { phase: ResolutionPhase::Contraction, iterations: 0 });
+
+        // Everything below only looks for errors.
+        if errors.stopped {
+            return tainted;
+        }
+
+        // A tainted variable now resolves to the error region, so any
+        // check involving it would just report the original error again.
+        let is_tainted = |r: Region<'tcx>| matches!(*r, ReVar(vid) if tainted.contains(&vid));
+
+        for (sub, sup, origin) in &self.data.verify_subregions {
+            if is_tainted(*sub) || is_tainted(*sup) {
+                continue;
+            }
+            let sub = var_data.normalize(self.tcx(), *sub);
+            let sup = var_data.normalize(self.tcx(), *sup);
+            if self.sub_concrete_regions(sub, sup) {
+                continue;
+            }
+
+            debug!(
+                "collect_errors: region error at {:?}: \
+                 cannot verify that {:?} <= {:?}",
+                origin, sub, sup
+            );
+
+            errors.push(RegionResolutionError::ConcreteFailure(origin.clone(), sub, sup));
+        }
+
+        for (sub, sup, origin) in &self.data.strict_subregions {
+            if is_tainted(*sub) || is_tainted(*sup) {
+                continue;
+            }
+            let sub = var_data.normalize(self.tcx(), *sub);
+            let sup = var_data.normalize(self.tcx(), *sup);
+            if sub != sup {
+                continue;
+            }
+
+            debug!(
+                "collect_errors: region error at {:?}: \
+                 cannot verify that {:?} < {:?}",
+                origin, sub, sup
+            );
+
+            errors.push(RegionResolutionError::StrictnessViolation(origin.clone(), sub, sup));
+        }
+
         // Check that all member constraints are satisfied.
         for member_constraint in &self.data.member_constraints {
+            if is_tainted(member_constraint.member_region) {
+                continue;
+            }
             let member_region = var_data.normalize(self.tcx(), member_constraint.member_region);
             let choice_regions = member_constraint
                 .choice_regions
@@ -633,8 +1278,15 @@ fn collect_errors(
             }
         }
 
+        if errors.stopped {
+            return tainted;
+        }
+
         for verify in &self.data.verifys {
             debug!("collect_errors: verify={:?}", verify);
+            if is_tainted(verify.region) {
+                continue;
+            }
             let sub = var_data.normalize(self.tcx(), verify.region);
 
             let verify_kind_ty = verify.kind.to_ty(self.tcx());
@@ -654,6 +1306,54 @@ fn collect_errors(
                 sub,
             ));
         }
+
+        tainted
+    }
+
+    /// Sets every variable that (transitively) must outlive a variable
+    /// that is already `ErrorValue` to `ErrorValue` as well, and returns
+    /// the set of variables changed this way. Such a variable got its
+    /// value from the poisoned one, so any conflict it has is just the
+    /// original error showing up again; reporting it only at the source
+    /// avoids a cascade of misleading errors.
+    ///
+    /// Only variables that passed their own upper-bound checks are
+    /// tainted: one that failed on its own keeps reporting its own
+    /// error. Variables that the poisoned one depends on, and other
+    /// parts of the graph, are left alone.
+    fn propagate_error_values(
+        &self,
+        graph: &RegionGraph<'tcx>,
+        var_data: &mut LexicalRegionResolutions<'tcx>,
+    ) -> FxHashSet<RegionVid> {
+        let mut tainted = FxHashSet::default();
+        let poisoned: Vec<RegionVid> = var_data
+            .values
+            .iter_enumerated()
+            .filter(|(_, value)| matches!(value, VarValue::ErrorValue))
+            .map(|(vid, _)| vid)
+            .collect();
+
+        for vid in poisoned {
+            for succ_index in graph.depth_traverse(var_node(graph, vid), OUTGOING) {
+                // Skip the dummy nodes for concrete regions.
+                if succ_index.0 >= self.num_vars() {
+                    continue;
+                }
+
+                let succ_vid = RegionVid::new(succ_index.0);
+                let succ_data = var_data.value_mut(succ_vid);
+                let succ_old = *succ_data;
+                if let VarValue::Value(_) = succ_old {
+                    debug!("propagate_error_values: {:?} tainted by {:?}", succ_vid, vid);
+                    *succ_data = VarValue::ErrorValue;
+                    self.note_update(var_data, succ_vid, succ_old, None);
+                    tainted.insert(succ_vid);
+                }
+            }
+        }
+
+        tainted
     }
 
     /// Go over the variables that were declared to be error variables
@@ -662,7 +1362,8 @@ fn collect_var_errors(
         &self,
         var_data: &LexicalRegionResolutions<'tcx>,
         graph: &RegionGraph<'tcx>,
-        errors: &mut Vec<RegionResolutionError<'tcx>>,
+        tainted: &FxHashSet<RegionVid>,
+        errors: &mut ErrorSink<'_, 'tcx>,
     ) {
         debug!("collect_var_errors, var_data = {:#?}", var_data.values);
 
@@ -680,8 +1381,16 @@ fn collect_var_errors(
         let mut dup_vec = IndexVec::from_elem_n(None, self.num_vars());
 
         for (node_vid, value) in var_data.values.iter_enumerated() {
+            if errors.stopped {
+                return;
+            }
+
             match *value {
                 VarValue::Value(_) => { /* Inference successful */ }
+                VarValue::ErrorValue if tainted.contains(&node_vid) => {
+                    // Poisoned by a variable it depends on; the error is
+                    // reported there.
+                }
                 VarValue::ErrorValue => {
                     // Inference impossible: this value contains
                     // inconsistent constraints.
@@ -713,45 +1422,7 @@ fn collect_var_errors(
     }
 
     fn construct_graph(&self) -> RegionGraph<'tcx> {
-        let num_vars = self.num_vars();
-
-        let mut graph = Graph::new();
-
-        for _ in 0..num_vars {
-            graph.add_node(());
-        }
-
-        // Issue #30438: two distinct dummy nodes, one for incoming
-        // edges (dummy_source) and another for outgoing edges
-        // (dummy_sink). In `dummy -> a -> b -> dummy`, using one
-        // dummy node leads one to think (erroneously) there exists a
-        // path from `b` to `a`. Two dummy nodes sidesteps the issue.
-        let dummy_source = graph.add_node(());
-        let dummy_sink = graph.add_node(());
-
-        for constraint in self.data.constraints.keys() {
-            match *constraint {
-                Constraint::VarSubVar(a_id, b_id) => {
-                    graph.add_edge(
-                        NodeIndex(a_id.index() as usize),
-                        NodeIndex(b_id.index() as usize),
-                        *constraint,
-                    );
-                }
-                Constraint::RegSubVar(_, b_id) => {
-                    graph.add_edge(dummy_source, NodeIndex(b_id.index() as usize), *constraint);
-                }
-                Constraint::VarSubReg(a_id, _) => {
-                    graph.add_edge(NodeIndex(a_id.index() as usize), dummy_sink, *constraint);
-                }
-                Constraint::RegSubReg(..) => {
-                    // this would be an edge from `dummy_source` to
-                    // `dummy_sink`; just ignore it.
-                }
-            }
-        }
-
-        graph
+        construct_region_graph(self.num_vars(), &self.data)
     }
 
     fn collect_error_for_expanding_node(
@@ -759,10 +1430,13 @@ fn collect_error_for_expanding_node(
         graph: &RegionGraph<'tcx>,
         dup_vec: &mut IndexVec<RegionVid, Option<RegionVid>>,
         node_idx: RegionVid,
-        errors: &mut Vec<RegionResolutionError<'tcx>>,
+        errors: &mut ErrorSink<'_, 'tcx>,
     ) {
         // Errors in expanding nodes result from a lower-bound that is
-        // not contained by an upper-bound.
+        // not contained by an upper-bound. (Variables only ever grow, so
+        // two upper bounds that have no GLB are not an error on their
+        // own: the variable can stay empty. Hence there is no separate
+        // "no GLB" error, and `RegionsNoOverlap` never reaches here.)
         let (mut lower_bounds, lower_vid_bounds, lower_dup) =
             self.collect_bounding_regions(graph, node_idx, INCOMING, Some(dup_vec));
         let (mut upper_bounds, _, upper_dup) =
@@ -787,36 +1461,42 @@ fn region_order_key(x: &RegionAndOrigin<'_>) -> u8 {
 
         let node_universe = self.var_infos[node_idx].universe;
 
-        for lower_bound in &lower_bounds {
-            let effective_lower_bound = if let ty::RePlaceholder(p) = lower_bound.region {
-                if node_universe.cannot_name(p.universe) {
-                    self.tcx().lifetimes.re_static
-                } else {
-                    lower_bound.region
-                }
-            } else {
-                lower_bound.region
-            };
-
-            for upper_bound in &upper_bounds {
-                if !self.sub_concrete_regions(effective_lower_bound, upper_bound.region) {
-                    let origin = self.var_infos[node_idx].origin;
-                    debug!(
-                        "region inference error at {:?} for {:?}: SubSupConflict sub: {:?} \
-                         sup: {:?}",
-                        origin, node_idx, lower_bound.region, upper_bound.region
-                    );
-                    errors.push(RegionResolutionError::SubSupConflict(
-                        node_idx,
-                        origin,
-                        lower_bound.origin.clone(),
-                        lower_bound.region,
-                        upper_bound.origin.clone(),
-                        upper_bound.region,
-                    ));
-                    return;
-                }
-            }
+        let spans = bound_spans(&lower_bounds, &upper_bounds);
+
+        // Normally, one conflict is enough to explain why `node_idx` has
+        // no value. With `-Z all-region-conflicts`, keep going, so that one
+        // failure doesn't hide another, unrelated one.
+        let conflicts = conflicting_bounds(
+            &lower_bounds,
+            &upper_bounds,
+            node_universe,
+            self.tcx().lifetimes.re_static,
+            self.tcx().sess.opts.debugging_opts.all_region_conflicts,
+            |sub, sup| self.sub_concrete_regions(sub, sup),
+        );
+        for &(lower_bound, upper_bound) in &conflicts {
+            let origin = self.var_infos[node_idx].origin;
+            debug!(
+                "region inference error at {:?} for {:?} (name hint {:?}): \
+                 SubSupConflict sub: {:?} sup: {:?}",
+                origin,
+                node_idx,
+                self.var_infos[node_idx].name_hint,
+                lower_bound.region,
+                upper_bound.region
+            );
+            errors.push(RegionResolutionError::SubSupConflict {
+                var: node_idx,
+                var_origin: origin,
+                sub_origin: lower_bound.origin.clone(),
+                sub_region: lower_bound.region,
+                sup_origin: upper_bound.origin.clone(),
+                sup_region: upper_bound.region,
+                spans: spans.clone(),
+            });
+        }
+        if !conflicts.is_empty() {
+            return;
         }
 
         // If we have a scenario like `exists<'a> { forall<'b> { 'b:
@@ -852,9 +1532,13 @@ fn region_order_key(x: &RegionAndOrigin<'_>) -> u8 {
             self.var_infos[node_idx].origin.span(),
             &format!(
                 "collect_error_for_expanding_node() could not find \
-                 error for var {:?} in universe {:?}, lower_bounds={:#?}, \
-                 upper_bounds={:#?}",
-                node_idx, node_universe, lower_bounds, upper_bounds
+                 error for var {:?} (name hint {:?}) in universe {:?}, \
+                 lower_bounds={:#?}, upper_bounds={:#?}",
+                node_idx,
+                self.var_infos[node_idx].name_hint,
+                node_universe,
+                lower_bounds,
+                upper_bounds
             ),
         );
     }
@@ -883,12 +1567,15 @@ struct WalkState<'tcx> {
             result: Vec<RegionAndOrigin<'tcx>>,
             dup_found: bool,
         }
-        let mut state = WalkState {
-            set: Default::default(),
-            stack: vec![orig_node_idx],
-            result: Vec::new(),
-            dup_found: false,
-        };
+        // Every edge of the starting node adds either a region to `result`
+        // or a variable to `set` and `stack`, so its degree is a cheap lower
+        // bound on how large they get.
+        let degree = graph.adjacent_edges(var_node(graph, orig_node_idx), dir).count();
+        let set = FxHashSet::with_capacity_and_hasher(degree + 1, Default::default());
+        let mut stack = Vec::with_capacity(degree + 1);
+        stack.push(orig_node_idx);
+        let mut state =
+            WalkState { set, stack, result: Vec::with_capacity(degree), dup_found: false };
         state.set.insert(orig_node_idx);
 
         // to start off the process, walk the source node in the
@@ -925,7 +1612,7 @@ fn process_edges<'tcx>(
         ) {
             debug!("process_edges(source_vid={:?}, dir={:?})", source_vid, dir);
 
-            let source_node_index = NodeIndex(source_vid.index() as usize);
+            let source_node_index = var_node(graph, source_vid);
             for (_, edge) in graph.adjacent_edges(source_node_index, dir) {
                 match edge.data {
                     Constraint::VarSubVar(from_vid, to_vid) => {
@@ -983,6 +1670,305 @@ fn bound_is_met(
     }
 }
 
+/// The expansion phase of lexical region resolution, which grows each
+/// variable until it contains all of its lower bounds. Unlike the rest of
+/// `LexicalResolver`, it needs no `TyCtxt`, just what is set up here.
+struct Expansion<'a, 'tcx> {
+    lattice: &'a dyn RegionLattice<'tcx>,
+    var_infos: &'a VarInfos,
+    data: &'a RegionConstraintData<'tcx>,
+    budget: &'a StepBudget,
+    re_static: Region<'tcx>,
+
+    /// The number of iterations to allow before deciding that expansion
+    /// does not converge; see `LexicalResolver::iteration_limit`.
+    iteration_limit: usize,
+
+    /// The item whose regions are being resolved, for `bug!` messages.
+    context: DefId,
+
+    emit: &'a dyn Fn(ResolutionEvent<'tcx>),
+}
+
+impl<'a, 'tcx> Expansion<'a, 'tcx> {
+    /// Grows each variable until it contains all of its lower bounds.
+    /// Values only ever grow, and each change to one is propagated along
+    /// the `VarSubVar` constraints out of the variable, in passes, until
+    /// a pass changes nothing.
+    ///
+    /// Variables are never collapsed into strongly connected components,
+    /// and there is no need to: if `'a <= 'b` and `'b <= 'a`, each keeps
+    /// being grown to the other's value until a fixed point is reached, so
+    /// all variables in a cycle end up with the same value. (If one of
+    /// them later becomes an error, `propagate_error_values` makes the
+    /// rest of the cycle errors too.)
+    fn run(&self, var_values: &mut LexicalRegionResolutions<'tcx>) {
+        (self.emit)(ResolutionEvent::PhaseStart(ResolutionPhase::Expansion));
+        let mut constraints = IndexVec::from_elem_n(Vec::new(), var_values.values.len());
+        let mut changes = Vec::new();
+        for constraint in self.data.constraints.keys() {
+            if !self.budget.charge(1) {
+                break;
+            }
+            let (a_vid, a_region, b_vid, b_data) = match *constraint {
+                Constraint::RegSubVar(a_region, b_vid) => {
+                    let b_data = var_values.value_mut(b_vid);
+                    (None, a_region, b_vid, b_data)
+                }
+                Constraint::VarSubVar(a_vid, b_vid) => match *var_values.value(a_vid) {
+                    VarValue::ErrorValue => continue,
+                    VarValue::Value(a_region) => {
+                        let b_data = var_values.value_mut(b_vid);
+                        (Some(a_vid), a_region, b_vid, b_data)
+                    }
+                },
+                Constraint::RegSubReg(..) | Constraint::VarSubReg(..) => {
+                    // These constraints are checked after expansion
+                    // is done, in `collect_errors`.
+                    continue;
+                }
+            };
+            let b_old = *b_data;
+            let changed = self.expand_node(a_region, b_vid, b_data);
+            if changed {
+                changes.push(b_vid);
+            }
+            if let Some(a_vid) = a_vid {
+                match *b_data {
+                    VarValue::Value(ReStatic) | VarValue::ErrorValue => (),
+                    _ => {
+                        constraints[a_vid].push((a_vid, b_vid));
+                        constraints[b_vid].push((a_vid, b_vid));
+                    }
+                }
+            }
+            if changed {
+                self.note_update(var_values, b_vid, b_old, Some(*constraint));
+            }
+        }
+        self.record_frame(var_values);
+
+        // Each pass handles the changes made by the previous one, and ends
+        // with a frame; each change handled counts as an iteration.
+        let mut iteration = 0;
+        'passes: while !changes.is_empty() {
+            for vid in mem::take(&mut changes) {
+                if !self.budget.charge(1) {
+                    break 'passes;
+                }
+                iteration += 1;
+                check_iteration_limit("expansion", iteration, self.iteration_limit, self.context);
+                constraints[vid].retain(|&(a_vid, b_vid)| {
+                    let a_region = match *var_values.value(a_vid) {
+                        VarValue::ErrorValue => return false,
+                        VarValue::Value(a_region) => a_region,
+                    };
+                    let b_data = var_values.value_mut(b_vid);
+                    let b_old = *b_data;
+                    let changed = self.expand_node(a_region, b_vid, b_data);
+                    let keep = !matches!(b_data, VarValue::Value(ReStatic) | VarValue::ErrorValue);
+                    if changed {
+                        changes.push(b_vid);
+                        let cause = Constraint::VarSubVar(a_vid, b_vid);
+                        self.note_update(var_values, b_vid, b_old, Some(cause));
+                    }
+                    keep
+                });
+            }
+            self.record_frame(var_values);
+        }
+        var_values.expansion_iterations = iteration;
+        (self.emit)(ResolutionEvent::PhaseEnd {
+            phase: ResolutionPhase::Expansion,
+            iterations: iteration,
+        });
+    }
+
+    /// Called after the value of `vid` has changed from `old_value`; see
+    /// `LexicalResolver::note_update`.
+    fn note_update(
+        &self,
+        var_values: &mut LexicalRegionResolutions<'tcx>,
+        vid: RegionVid,
+        old_value: VarValue<'tcx>,
+        cause: Option<Constraint<'tcx>>,
+    ) {
+        var_values.record_step(vid, old_value, cause);
+        (self.emit)(ResolutionEvent::NodeUpdated {
+            vid,
+            from: old_value.as_region(),
+            to: var_values.value(vid).as_region(),
+        });
+    }
+
+    /// Appends the current state of `var_values` to its frames, if
+    /// `-Z dump-region-frames` was given. This happens once after the
+    /// first pass over the constraints, and once after each later pass.
+    fn record_frame(&self, var_values: &mut LexicalRegionResolutions<'tcx>) {
+        if var_values.frames.is_none() {
+            return;
+        }
+        let frame = self.render_frame(var_values);
+        var_values.frames.as_mut().unwrap().push(frame);
+    }
+
+    /// Renders the constraint graph in DOT format, with each variable
+    /// labeled by its current value. Concrete regions get a node of their
+    /// own per constraint, so that unrelated uses of, say, `'static` do
+    /// not look connected.
+    fn render_frame(&self, var_values: &LexicalRegionResolutions<'tcx>) -> String {
+        let mut out = String::from("digraph region_values {\n");
+        for (vid, value) in var_values.values.iter_enumerated() {
+            let value = match value {
+                VarValue::Value(r) => format!("{:?}", r),
+                VarValue::ErrorValue => "<error>".to_string(),
+            };
+            out.push_str(&format!(
+                "    v{} [label=\"{:?} = {}\"];\n",
+                vid.index(),
+                vid,
+                value.escape_default()
+            ));
+        }
+        for (index, constraint) in self.data.constraints.keys().enumerate() {
+            let (from, to) = match *constraint {
+                Constraint::VarSubVar(a, b) => {
+                    (format!("v{}", a.index()), format!("v{}", b.index()))
+                }
+                Constraint::RegSubVar(r, b) => {
+                    out.push_str(&format!(
+                        "    r{} [shape=box, label=\"{}\"];\n",
+                        index,
+                        format!("{:?}", r).escape_default()
+                    ));
+                    (format!("r{}", index), format!("v{}", b.index()))
+                }
+                Constraint::VarSubReg(a, r) => {
+                    out.push_str(&format!(
+                        "    r{} [shape=box, label=\"{}\"];\n",
+                        index,
+                        format!("{:?}", r).escape_default()
+                    ));
+                    (format!("v{}", a.index()), format!("r{}", index))
+                }
+                Constraint::RegSubReg(..) => continue,
+            };
+            out.push_str(&format!("    {} -> {};\n", from, to));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn expand_node(
+        &self,
+        a_region: Region<'tcx>,
+        b_vid: RegionVid,
+        b_data: &mut VarValue<'tcx>,
+    ) -> bool {
+        debug!("expand_node({:?}, {:?} == {:?})", a_region, b_vid, b_data);
+
+        match *a_region {
+            // Check if this relationship is implied by a given.
+            ty::ReEarlyBound(_) | ty::ReFree(_) => {
+                if self.data.givens.contains(&(a_region, b_vid)) {
+                    debug!("given");
+                    return false;
+                }
+            }
+
+            _ => {}
+        }
+
+        match *b_data {
+            VarValue::Value(cur_region) => {
+                // This is a specialized version of the `lub_concrete_regions`
+                // check below for a common case, here purely as an
+                // optimization.
+                let b_universe = self.var_infos[b_vid].universe;
+                if let ReEmpty(a_universe) = a_region {
+                    if *a_universe == b_universe {
+                        return false;
+                    }
+                }
+
+                let mut lub = self.lub_concrete_regions(a_region, cur_region);
+                if lub == cur_region {
+                    return false;
+                }
+
+                // Watch out for `'b: !1` relationships, where the
+                // universe of `'b` can't name the placeholder `!1`. In
+                // that case, we have to grow `'b` to be `'static` for the
+                // relationship to hold. This is obviously a kind of sub-optimal
+                // choice -- in the future, when we incorporate a knowledge
+                // of the parameter environment, we might be able to find a
+                // tighter bound than `'static`.
+                //
+                // (This might e.g. arise from being asked to prove `for<'a> { 'b: 'a }`.)
+                if let ty::RePlaceholder(p) = lub {
+                    if b_universe.cannot_name(p.universe) {
+                        lub = self.re_static;
+                    }
+                }
+
+                debug!("Expanding value of {:?} from {:?} to {:?}", b_vid, cur_region, lub);
+
+                *b_data = VarValue::Value(lub);
+                true
+            }
+
+            VarValue::ErrorValue => false,
+        }
+    }
+
+    fn lub_concrete_regions(&self, a: Region<'tcx>, b: Region<'tcx>) -> Region<'tcx> {
+        lub_concrete_regions(self.lattice, self.var_infos, a, b)
+    }
+}
+
+/// Returns the least-upper-bound of `a` and `b` in `lattice`; i.e., the
+/// smallest region `c` such that `a <= c` and `b <= c`.
+///
+/// Neither `a` nor `b` may be an inference variable (hence the
+/// term "concrete regions").
+fn lub_concrete_regions<'tcx>(
+    lattice: &dyn RegionLattice<'tcx>,
+    var_infos: &VarInfos,
+    a: Region<'tcx>,
+    b: Region<'tcx>,
+) -> Region<'tcx> {
+    match (a, b) {
+        (&ReVar(v_id), _) | (_, &ReVar(v_id)) => {
+            let origin = var_infos[v_id].origin;
+            span_bug!(
+                origin.span(),
+                "lub_concrete_regions invoked with non-concrete \
+                 regions: LUB({:?}, {:?}) involves {:?}, which has origin {:?}",
+                a,
+                b,
+                v_id,
+                origin
+            );
+        }
+
+        _ => lattice.lub_concrete_regions(a, b),
+    }
+}
+
+/// Hitting the limit means some value went up and back down again
+/// (e.g., an asymmetric LUB), so report it rather than spinning forever.
+fn check_iteration_limit(tag: &str, iteration: usize, limit: usize, context: DefId) {
+    if iteration > limit {
+        bug!(
+            "{}: region inference did not reach a fixed point after {} iterations \
+             (context={:?})",
+            tag,
+            iteration,
+            context,
+        );
+    }
+}
+
 impl<'tcx> fmt::Debug for RegionAndOrigin<'tcx> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "RegionAndOrigin({:?},{:?})", self.region, self.origin)
@@ -1008,6 +1994,99 @@ fn value_mut(&mut self, rid: RegionVid) -> &mut VarValue<'tcx> {
         &mut self.values[rid]
     }
 
+    /// Records that the value of `rid` just changed from `old_value`, if
+    /// tracing is enabled.
+    fn record_step(
+        &mut self,
+        rid: RegionVid,
+        old_value: VarValue<'tcx>,
+        cause: Option<Constraint<'tcx>>,
+    ) {
+        if let Some(trace) = &mut self.trace {
+            trace[rid].push(TraceStep {
+                vid: rid,
+                old_value: old_value.as_region(),
+                new_value: self.values[rid].as_region(),
+                cause,
+            });
+        }
+    }
+
+    /// Returns every change made to the value of `rid` during resolution,
+    /// oldest first, so the last step (if any) ends at the value that was
+    /// inferred. This is always empty unless `-Z trace-region-values` was
+    /// given.
+    pub fn value_trace(&self, rid: RegionVid) -> &[TraceStep<'tcx>] {
+        match &self.trace {
+            Some(trace) => &trace[rid],
+            None => &[],
+        }
+    }
+
+    /// Records which variables nothing is required to be contained in:
+    /// those with no incoming edge in `graph`, and no member constraint
+    /// that could give them a value. Pruned variables are left out, since
+    /// their constraints were dropped rather than absent.
+    fn note_unconstrained_vars(
+        &mut self,
+        graph: &RegionGraph<'tcx>,
+        data: &RegionConstraintData<'tcx>,
+    ) {
+        let members: FxHashSet<Region<'tcx>> =
+            data.member_constraints.iter().map(|c| c.member_region).collect();
+        self.unconstrained = self
+            .values
+            .indices()
+            .filter(|vid| {
+                !self.pruned.contains(vid)
+                    && !members.contains(&ty::ReVar(*vid))
+                    && graph.adjacent_edges(var_node(graph, *vid), INCOMING).next().is_none()
+            })
+            .collect();
+    }
+
+    /// Classifies the value inferred for each variable, so callers can
+    /// react to each one without looking at the errors.
+    pub fn var_statuses(&self) -> IndexVec<RegionVid, VarStatus<'tcx>> {
+        self.values
+            .iter_enumerated()
+            .map(|(vid, value)| match *value {
+                VarValue::ErrorValue => VarStatus::Errored,
+                VarValue::Value(_) if self.pruned.contains(&vid) => VarStatus::Pruned,
+                VarValue::Value(_) if self.unconstrained.contains(&vid) => VarStatus::Unconstrained,
+                VarValue::Value(r) => VarStatus::Resolved(r),
+            })
+            .collect()
+    }
+
+    /// Returns a snapshot of the constraint graph in DOT format for each
+    /// pass of expansion (see `Expansion::record_frame`), oldest first,
+    /// with every variable labeled by its value at that point. This is
+    /// always empty unless `-Z dump-region-frames` was given.
+    pub fn value_frames(&self) -> &[String] {
+        self.frames.as_deref().unwrap_or(&[])
+    }
+
+    /// Returns how many iterations expansion took to reach a fixed point,
+    /// not counting the first pass over the constraints. This is zero if
+    /// the regions were erased rather than solved.
+    pub fn expansion_iterations(&self) -> usize {
+        self.expansion_iterations
+    }
+
+    /// Returns the size of the region inference problem that was solved,
+    /// as `RegionConstraintCollector::region_graph_stats` would have before
+    /// resolution, along with how many iterations expansion took. This is
+    /// `None` if the regions were erased rather than solved.
+    pub fn graph_stats(&self) -> Option<RegionGraphStats> {
+        let stats = self.graph_stats?;
+        Some(RegionGraphStats { expansion_iterations: Some(self.expansion_iterations), ..stats })
+    }
+
+    /// Returns the value inferred for `rid`, or `'static` if no value could
+    /// be inferred. The values are fixed once resolution is done, so this
+    /// is a plain read, and can be called through any number of shared
+    /// borrows at once.
     pub fn resolve_var(&self, rid: RegionVid) -> ty::Region<'tcx> {
         let result = match self.values[rid] {
             VarValue::Value(r) => r,
@@ -1016,4 +2095,99 @@ pub fn resolve_var(&self, rid: RegionVid) -> ty::Region<'tcx> {
         debug!("resolve_var({:?}) = {:?}", rid, result);
         result
     }
+
+    /// Returns the resolved value of every region variable, indexed by
+    /// `RegionVid`. Combined with `diff_solutions`, this is handy for
+    /// tracking down region inference results that change between runs.
+    pub fn snapshot_solution(&self) -> Vec<Region<'tcx>> {
+        self.values.indices().map(|vid| self.resolve_var(vid)).collect()
+    }
+
+    /// Drops everything but the resolved value of each variable (see
+    /// `resolve_var`), for callers that want to hold on to the solution
+    /// or pass it around.
+    pub fn into_solution(self) -> RegionSolution<'tcx> {
+        RegionSolution { values: self.values.indices().map(|vid| self.resolve_var(vid)).collect() }
+    }
+
+    /// Cross-checks the values of the variables created by `lub_regions`
+    /// (see `RegionConstraintStorage::combinations`) against computing the
+    /// LUB of the values of their operands directly, returning a
+    /// description of the first mismatch. A mismatch points at a bug in
+    /// either `combine_vars` or expansion.
+    ///
+    /// The variable's value must contain the LUB, but need not be equal
+    /// to it: nothing stops other constraints from growing the variable
+    /// further. GLB variables are not checked here, since their upper
+    /// bounds are ordinary constraints, checked by `collect_errors`.
+    /// Variables whose value (or whose operands' value) could not be
+    /// inferred are skipped, as are those dropped by `prune_to_roots`.
+    pub fn check_combinations(
+        &self,
+        region_rels: &RegionRelations<'_, 'tcx>,
+        combinations: &[(RegionVid, CombineMapType, Region<'tcx>, Region<'tcx>)],
+    ) -> Result<(), String> {
+        let value = |r: Region<'tcx>| match *r {
+            ty::ReVar(vid) => self.values[vid].as_region(),
+            _ => Some(r),
+        };
+        for &(c, t, a, b) in combinations {
+            if t != CombineMapType::Lub || self.pruned.contains(&c) {
+                continue;
+            }
+            let (c_value, a_value, b_value) =
+                match (self.values[c].as_region(), value(a), value(b)) {
+                    (Some(c), Some(a), Some(b)) => (c, a, b),
+                    _ => continue,
+                };
+            let lub = region_rels.lub_concrete_regions(a_value, b_value);
+            if !region_rels.sub_concrete_regions(lub, c_value) {
+                return Err(format!(
+                    "{:?} is the LUB of {:?} and {:?}, which resolve to {:?} and {:?}, \
+                     but it resolved to {:?}, which does not contain {:?}",
+                    c, a, b, a_value, b_value, c_value, lub
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The values lexical region resolution inferred for each region variable;
+/// see `LexicalRegionResolutions::into_solution`.
+#[derive(Clone, Debug)]
+pub struct RegionSolution<'tcx> {
+    values: IndexVec<RegionVid, Region<'tcx>>,
+}
+
+impl<'tcx> RegionSolution<'tcx> {
+    pub fn resolve(&self, rid: RegionVid) -> Region<'tcx> {
+        self.values[rid]
+    }
+
+    pub fn num_vars(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The value of every variable, for callers that look up many of them
+    /// and would rather index directly than call `resolve` each time.
+    pub fn values(&self) -> &IndexVec<RegionVid, Region<'tcx>> {
+        &self.values
+    }
+}
+
+/// Compares two solutions captured with `snapshot_solution`, returning
+/// each variable that was resolved differently together with its value
+/// in `a` and in `b`. Variables past the end of the shorter solution are
+/// not compared.
+pub fn diff_solutions<'tcx>(
+    a: &[Region<'tcx>],
+    b: &[Region<'tcx>],
+) -> Vec<(RegionVid, Region<'tcx>, Region<'tcx>)> {
+    a.iter()
+        .zip(b)
+        .enumerate()
+        .filter(|&(_, (a_region, b_region))| a_region != b_region)
+        .map(|(index, (&a_region, &b_region))| (RegionVid::new(index), a_region, b_region))
+        .collect()
 }
@@ -0,0 +1,541 @@
+use super::*;
+use crate::infer::region_constraints::{RegionVariableInfo, Verify};
+
+use rustc_hir::def_id::CRATE_DEF_ID;
+use rustc_span::{BytePos, DUMMY_SP};
+
+// None of the code under test needs a `TyCtxt`, so the regions used here
+// are leaked rather than interned.
+fn region(kind: ty::RegionKind) -> Region<'static> {
+    Box::leak(Box::new(kind))
+}
+
+fn free_region(index: u32) -> Region<'static> {
+    let scope = CRATE_DEF_ID.to_def_id();
+    region(ty::ReFree(ty::FreeRegion { scope, bound_region: ty::BrAnon(index) }))
+}
+
+fn origin() -> SubregionOrigin<'static> {
+    SubregionOrigin::RelateRegionParamBound(DUMMY_SP)
+}
+
+fn var_infos(universes: &[ty::UniverseIndex]) -> VarInfos {
+    universes
+        .iter()
+        .map(|&universe| RegionVariableInfo {
+            origin: RegionVariableOrigin::MiscVariable(DUMMY_SP),
+            universe,
+            name_hint: None,
+        })
+        .collect()
+}
+
+fn var(index: u32) -> RegionVid {
+    RegionVid::from_u32(index)
+}
+
+/// Orders regions in a chain: the empty regions, then free regions by
+/// index, then `'static`. Anything else is not expected here.
+struct ChainLattice;
+
+impl RegionLattice<'static> for ChainLattice {
+    fn lub_concrete_regions(&self, a: Region<'static>, b: Region<'static>) -> Region<'static> {
+        let rank = |r: Region<'static>| match *r {
+            // The empty regions of nested universes are the smaller ones.
+            ReEmpty(universe) => (0, u32::MAX - universe.as_u32()),
+            ReFree(ty::FreeRegion { bound_region: ty::BrAnon(index), .. }) => (1, index),
+            ReStatic => (2, 0),
+            _ => panic!("unexpected region {:?}", r),
+        };
+        if rank(a) >= rank(b) { a } else { b }
+    }
+}
+
+/// The values resolution of `data` starts from: every variable is the
+/// empty region of its universe. Frames are recorded.
+fn initial_values(
+    var_infos: &VarInfos,
+    data: &RegionConstraintData<'static>,
+) -> LexicalRegionResolutions<'static> {
+    LexicalRegionResolutions {
+        values: var_infos
+            .iter()
+            .map(|info| VarValue::Value(region(ReEmpty(info.universe))))
+            .collect(),
+        error_region: region(ReStatic),
+        trace: None,
+        frames: Some(vec![]),
+        pruned: FxHashSet::default(),
+        unconstrained: FxHashSet::default(),
+        expansion_iterations: 0,
+        graph_stats: Some(data.graph_stats(var_infos.len())),
+    }
+}
+
+fn ignore_event(_: ResolutionEvent<'static>) {}
+
+/// Expansion over `data` in `ChainLattice`, with no iteration limit, and
+/// no one listening for events.
+fn expansion<'a>(
+    var_infos: &'a VarInfos,
+    data: &'a RegionConstraintData<'static>,
+    budget: &'a StepBudget,
+) -> Expansion<'a, 'static> {
+    Expansion {
+        lattice: &ChainLattice,
+        var_infos,
+        data,
+        budget,
+        re_static: region(ReStatic),
+        iteration_limit: usize::MAX,
+        context: CRATE_DEF_ID.to_def_id(),
+        emit: &ignore_event,
+    }
+}
+
+/// Runs expansion over `data`, with root universe variables, and returns
+/// the values it arrived at.
+fn expand(
+    num_vars: usize,
+    data: &RegionConstraintData<'static>,
+    budget: &StepBudget,
+) -> LexicalRegionResolutions<'static> {
+    let var_infos = var_infos(&vec![ty::UniverseIndex::ROOT; num_vars]);
+    let mut values = initial_values(&var_infos, data);
+    expansion(&var_infos, data, budget).run(&mut values);
+    values
+}
+
+/// `'a <= v0 <= v1 <= v2`.
+fn chain() -> RegionConstraintData<'static> {
+    let mut data = RegionConstraintData::default();
+    data.constraints.insert(Constraint::RegSubVar(free_region(0), var(0)), origin());
+    data.constraints.insert(Constraint::VarSubVar(var(0), var(1)), origin());
+    data.constraints.insert(Constraint::VarSubVar(var(1), var(2)), origin());
+    data
+}
+
+#[test]
+fn step_budget_stops_at_its_limit() {
+    let budget = StepBudget::new(Some(5));
+    assert!(budget.charge(1));
+    assert!(budget.charge(3));
+    assert!(!budget.exhausted());
+
+    // Asking for more than is left counts only what is left, so a
+    // resolution that runs out reports exactly the budget it was given.
+    assert!(!budget.charge(2));
+    assert!(budget.exhausted());
+    assert_eq!(budget.steps(), 5);
+
+    // Once exhausted, even a step that would have fit is refused.
+    assert!(!budget.charge(0));
+    assert_eq!(budget.steps(), 5);
+}
+
+#[test]
+fn step_budget_can_be_used_up_exactly() {
+    let budget = StepBudget::new(Some(2));
+    assert!(budget.charge(2));
+    assert!(!budget.exhausted());
+    assert!(!budget.charge(1));
+    assert_eq!(budget.steps(), 2);
+}
+
+#[test]
+fn unlimited_step_budget_counts_steps() {
+    let budget = StepBudget::new(None);
+    assert!(budget.charge(usize::MAX / 2));
+    assert!(budget.charge(1));
+    assert!(!budget.exhausted());
+    assert_eq!(budget.steps(), usize::MAX / 2 + 1);
+}
+
+#[test]
+fn region_values_counted_once_each() {
+    let root = ty::UniverseIndex::ROOT;
+    let (a, b, c) = (free_region(0), free_region(1), free_region(2));
+    let (v0, v1) = (RegionVid::from(0u32), RegionVid::from(1u32));
+    let mut data = RegionConstraintData::default();
+    data.constraints.insert(Constraint::RegSubVar(a, v0), origin());
+    data.constraints.insert(Constraint::VarSubReg(v0, b), origin());
+    data.constraints.insert(Constraint::RegSubVar(a, v1), origin());
+    data.constraints.insert(Constraint::VarSubVar(v0, v1), origin());
+    let mut free_regions = FreeRegionMap::default();
+
+    // `'a` and `'b`, the empty region of the root universe and `'static`.
+    assert_eq!(num_region_values(&var_infos(&[root, root]), &data, &free_regions), 4);
+
+    // The LUB of two free regions can be any region the map relates them
+    // to, so those count too, but only once.
+    free_regions.relate_regions(a, c);
+    free_regions.relate_regions(b, c);
+    assert_eq!(num_region_values(&var_infos(&[root, root]), &data, &free_regions), 5);
+
+    // Each universe has an empty region of its own.
+    let nested = root.next_universe();
+    assert_eq!(num_region_values(&var_infos(&[root, nested]), &data, &free_regions), 6);
+}
+
+/// The first pass over the constraints (`VarSubVar` ones come first)
+/// only reaches `v0`; each later pass takes the value one variable
+/// further, and the last one changes nothing. There is a frame after
+/// every pass, and an iteration for every change handled.
+#[test]
+fn one_frame_per_expansion_pass() {
+    let values = expand(3, &chain(), &StepBudget::new(None));
+    let a = free_region(0);
+    for vid in values.values.indices() {
+        assert_eq!(values.resolve_var(vid), a);
+    }
+    assert_eq!(values.expansion_iterations(), 3);
+
+    let frames = values.value_frames();
+    assert_eq!(frames.len(), 4);
+    let node = |index: u32, value: Region<'static>| {
+        let label = format!("{:?} = {:?}", var(index), value);
+        format!("    v{} [label=\"{}\"];\n", index, label.escape_default())
+    };
+    let empty = region(ReEmpty(ty::UniverseIndex::ROOT));
+    assert!(frames[0].starts_with("digraph region_values {\n"), "{}", frames[0]);
+    assert!(frames[0].contains(&node(0, a)), "{}", frames[0]);
+    assert!(frames[0].contains(&node(1, empty)), "{}", frames[0]);
+    assert!(frames[1].contains(&node(1, a)), "{}", frames[1]);
+    assert!(frames[1].contains(&node(2, empty)), "{}", frames[1]);
+    assert!(frames[2].contains(&node(2, a)), "{}", frames[2]);
+    assert_eq!(frames[2], frames[3]);
+    assert!(frames[3].contains("    v0 -> v1;\n"), "{}", frames[3]);
+}
+
+/// Each constraint of the first pass is a step, and so is each change
+/// handled after it: a budget of four is used up before `v2` grows.
+#[test]
+fn expansion_stops_when_the_budget_runs_out() {
+    let budget = StepBudget::new(Some(4));
+    let values = expand(3, &chain(), &budget);
+    assert!(budget.exhausted());
+    assert_eq!(budget.steps(), 4);
+    assert_eq!(values.expansion_iterations(), 1);
+    let a = free_region(0);
+    assert_eq!(values.resolve_var(var(0)), a);
+    assert_eq!(values.resolve_var(var(1)), a);
+    assert_eq!(values.resolve_var(var(2)), region(ReEmpty(ty::UniverseIndex::ROOT)));
+}
+
+#[test]
+fn graph_stats_count_expansion_iterations() {
+    let values = expand(3, &chain(), &StepBudget::new(None));
+    let expected = RegionGraphStats {
+        num_vars: 3,
+        var_sub_var: 2,
+        reg_sub_var: 1,
+        var_sub_reg: 0,
+        reg_sub_reg: 0,
+        num_sccs: 3,
+        max_degree: 2,
+        expansion_iterations: Some(3),
+    };
+    assert_eq!(values.graph_stats(), Some(expected));
+}
+
+/// With `v0` as the only root, `v1` is kept through `v0 <= v1`, but `v2`
+/// and `v3` are not, and neither is anything that mentions them. Pruned
+/// variables keep the empty region they started out with.
+#[test]
+fn pruning_drops_vars_not_connected_to_a_root() {
+    let (a, b) = (free_region(0), free_region(1));
+    let mut data = RegionConstraintData::default();
+    data.constraints.insert(Constraint::RegSubVar(a, var(0)), origin());
+    data.constraints.insert(Constraint::VarSubVar(var(0), var(1)), origin());
+    data.constraints.insert(Constraint::RegSubVar(a, var(2)), origin());
+    data.constraints.insert(Constraint::VarSubReg(var(2), b), origin());
+    data.verify_subregions.push((region(ReVar(var(2))), region(ReVar(var(0))), origin()));
+    data.roots.push(var(0));
+    let var_infos = var_infos(&[ty::UniverseIndex::ROOT; 4]);
+    let mut values = initial_values(&var_infos, &data);
+
+    prune_to_roots(&mut data, &mut values);
+    let mut pruned: Vec<_> = values.pruned.iter().map(|vid| vid.index()).collect();
+    pruned.sort();
+    assert_eq!(pruned, [2, 3]);
+    let constraints: Vec<_> = data.constraints.keys().copied().collect();
+    assert_eq!(
+        constraints,
+        [Constraint::VarSubVar(var(0), var(1)), Constraint::RegSubVar(a, var(0))]
+    );
+    assert!(data.verify_subregions.is_empty());
+    let empty = region(ReEmpty(ty::UniverseIndex::ROOT));
+    assert_eq!(values.resolve_var(var(2)), empty);
+    assert_eq!(values.resolve_var(var(3)), empty);
+}
+
+/// Only a variable with no lower bound at all is unconstrained: one whose
+/// lower bound is an empty region is resolved to it, and one dropped by
+/// `prune_to_roots` is reported as such, although both are empty too.
+#[test]
+fn var_statuses_tell_apart_unconstrained_vars() {
+    let root = ty::UniverseIndex::ROOT;
+    let nested = root.next_universe();
+    let var_infos = var_infos(&[root, root, nested, root, root]);
+    let (a, b, root_empty) = (free_region(0), free_region(1), region(ReEmpty(root)));
+    let mut data = RegionConstraintData::default();
+    data.constraints.insert(Constraint::RegSubVar(a, var(0)), origin());
+    data.constraints.insert(Constraint::VarSubReg(var(1), b), origin());
+    data.constraints.insert(Constraint::RegSubVar(root_empty, var(2)), origin());
+    data.constraints.insert(Constraint::RegSubVar(a, var(4)), origin());
+    data.constraints.insert(Constraint::VarSubReg(var(4), b), origin());
+
+    let mut values = initial_values(&var_infos, &data);
+    values.pruned.insert(var(3));
+    values.note_unconstrained_vars(&construct_region_graph(var_infos.len(), &data), &data);
+    values.values[var(0)] = VarValue::Value(a);
+    values.values[var(2)] = VarValue::Value(root_empty);
+    values.values[var(4)] = VarValue::ErrorValue;
+
+    let statuses: Vec<_> = values.var_statuses().into_iter().collect();
+    assert_eq!(
+        statuses,
+        [
+            VarStatus::Resolved(a),
+            VarStatus::Unconstrained,
+            VarStatus::Resolved(root_empty),
+            VarStatus::Pruned,
+            VarStatus::Errored,
+        ]
+    );
+}
+
+/// Both lower bounds and the upper bound contributed to the conflict, so
+/// all of their spans are recorded, each once and in order.
+#[test]
+fn conflicts_record_the_spans_of_all_bounds() {
+    let span = |lo| Span::with_root_ctxt(BytePos(lo), BytePos(lo + 1));
+    let bound = |index, lo| RegionAndOrigin {
+        region: free_region(index),
+        origin: SubregionOrigin::RelateRegionParamBound(span(lo)),
+    };
+    let lower = [bound(2, 3), bound(1, 1)];
+    let upper = [bound(0, 2), bound(3, 1)];
+    assert_eq!(*bound_spans(&lower, &upper), [span(1), span(2), span(3)]);
+}
+
+/// Neither `'b` nor `'c` fits in `'a` or in the empty region; returns
+/// the lower and upper bound of each conflict `conflicting_bounds` finds.
+fn conflicts(all_conflicts: bool) -> Vec<(Region<'static>, Region<'static>)> {
+    let bound = |region| RegionAndOrigin { region, origin: origin() };
+    let lower = [bound(free_region(1)), bound(free_region(2))];
+    let upper = [bound(free_region(0)), bound(region(ReEmpty(ty::UniverseIndex::ROOT)))];
+    let is_sub = |sub, sup| ChainLattice.sub_concrete_regions(sub, sup);
+    let root = ty::UniverseIndex::ROOT;
+    conflicting_bounds(&lower, &upper, root, region(ReStatic), all_conflicts, is_sub)
+        .into_iter()
+        .map(|(lower, upper)| (lower.region, upper.region))
+        .collect()
+}
+
+/// One conflict is enough to explain why a variable has no value.
+#[test]
+fn first_region_conflict_only() {
+    assert_eq!(conflicts(false), [(free_region(1), free_region(0))]);
+}
+
+/// Once `'b`/`'a` is reported, `'c`/`'empty` is the only conflict left
+/// that involves neither.
+#[test]
+fn all_region_conflicts_reports_disjoint_conflicts() {
+    let empty = region(ReEmpty(ty::UniverseIndex::ROOT));
+    let expected = [(free_region(1), free_region(0)), (free_region(2), empty)];
+    assert_eq!(conflicts(true), expected);
+}
+
+/// A verified constraint is checked against the values inference picks,
+/// but does not make those values any larger.
+#[test]
+fn verified_constraints_do_not_drive_inference() {
+    let mut data = RegionConstraintData::default();
+    data.verify_subregions.push((free_region(0), region(ReVar(var(0))), origin()));
+    let values = expand(1, &data, &StepBudget::new(None));
+    assert_eq!(values.resolve_var(var(0)), region(ReEmpty(ty::UniverseIndex::ROOT)));
+    assert_eq!(values.expansion_iterations(), 0);
+}
+
+/// Errors can be told apart without matching on them.
+#[test]
+fn errors_have_a_kind() {
+    let failure = RegionResolutionError::ConcreteFailure(origin(), free_region(1), free_region(0));
+    assert_eq!(failure.kind(), RegionResolutionErrorKind::ConcreteFailure);
+    let timeout = RegionResolutionError::Timeout { iterations: 1, node_count: 1 };
+    assert_eq!(timeout.kind(), RegionResolutionErrorKind::Timeout);
+}
+
+/// An error has the category of the origin of its constraint, and one
+/// that comes from no single constraint has none.
+#[test]
+fn errors_have_the_category_of_their_origin() {
+    let (a, b) = (free_region(0), free_region(1));
+    let bound = RegionResolutionError::ConcreteFailure(origin(), b, a);
+    assert_eq!(bound.category(), RegionConstraintCategory::Bound);
+    let call_return = SubregionOrigin::CallReturn(DUMMY_SP);
+    let call_return = RegionResolutionError::ConcreteFailure(call_return, b, a);
+    assert_eq!(call_return.category(), RegionConstraintCategory::CallReturn);
+    let timeout = RegionResolutionError::Timeout { iterations: 1, node_count: 1 };
+    assert_eq!(timeout.category(), RegionConstraintCategory::Unknown);
+}
+
+/// Once the error handler asks to stop, it is not called again.
+#[test]
+fn error_handler_can_stop_early() {
+    for &(stop, expected) in &[(false, 2), (true, 1)] {
+        let mut calls = 0;
+        let mut on_error = |_: RegionResolutionError<'static>| {
+            calls += 1;
+            if stop { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        };
+        let mut errors = ErrorSink { on_error: &mut on_error, stopped: false };
+        for &(sub, sup) in &[(free_region(1), free_region(0)), (free_region(2), free_region(0))] {
+            errors.push(RegionResolutionError::ConcreteFailure(origin(), sub, sup));
+        }
+        assert_eq!(errors.stopped, stop);
+        assert_eq!(calls, expected);
+    }
+}
+
+/// Needing more iterations than the limit means resolution failed to
+/// converge, which is a compiler bug, so it ICEs rather than carrying on.
+#[test]
+#[should_panic(expected = "did not reach a fixed point after 3 iterations")]
+fn iteration_limit_is_a_bug() {
+    let (data, budget) = (chain(), StepBudget::new(None));
+    let var_infos = var_infos(&[ty::UniverseIndex::ROOT; 3]);
+    let expansion = Expansion { iteration_limit: 2, ..expansion(&var_infos, &data, &budget) };
+    expansion.run(&mut initial_values(&var_infos, &data));
+}
+
+/// A variable standing in for a concrete region is a bug, and the panic
+/// says which variable it was and where it came from.
+#[test]
+#[should_panic(expected = "involves '_#1r, which has origin MiscVariable")]
+fn non_concrete_lub_names_the_variable() {
+    let var_infos = var_infos(&[ty::UniverseIndex::ROOT; 2]);
+    lub_concrete_regions(&ChainLattice, &var_infos, free_region(0), region(ReVar(var(1))));
+}
+
+/// Two runs over the same constraints should agree; where they do not,
+/// `diff_solutions` points at the variables that differ.
+#[test]
+fn diff_solutions_lists_changed_vars() {
+    let (a, b) = (free_region(0), free_region(1));
+    let first = [a, a, a];
+    assert!(diff_solutions(&first, &[a, a, a]).is_empty());
+    assert_eq!(diff_solutions(&first, &[a, a, b]), [(var(2), a, b)]);
+    assert!(diff_solutions(&first, &first[..1]).is_empty());
+}
+
+/// Each variable in the chain grows once, and the trace says which
+/// constraint made it grow.
+#[test]
+fn value_trace_names_the_constraint() {
+    let (data, budget) = (chain(), StepBudget::new(None));
+    let var_infos = var_infos(&[ty::UniverseIndex::ROOT; 3]);
+    let mut values = initial_values(&var_infos, &data);
+    values.trace = Some(IndexVec::from_elem_n(Vec::new(), 3));
+    expansion(&var_infos, &data, &budget).run(&mut values);
+
+    let a = free_region(0);
+    let causes = [
+        Constraint::RegSubVar(a, var(0)),
+        Constraint::VarSubVar(var(0), var(1)),
+        Constraint::VarSubVar(var(1), var(2)),
+    ];
+    for (vid, &cause) in values.values.indices().zip(&causes) {
+        let trace = values.value_trace(vid);
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].vid, vid);
+        assert_eq!(trace[0].old_value, Some(region(ReEmpty(ty::UniverseIndex::ROOT))));
+        assert_eq!(trace[0].new_value, Some(a));
+        assert_eq!(trace[0].cause, Some(cause));
+    }
+}
+
+/// A lattice in which distinct non-empty regions are unrelated, so that
+/// their only upper bound is `'static`.
+struct FlatLattice;
+
+impl RegionLattice<'static> for FlatLattice {
+    fn lub_concrete_regions(&self, a: Region<'static>, b: Region<'static>) -> Region<'static> {
+        match (a, b) {
+            (&ReEmpty(_), _) | (_, &ReEmpty(_)) => ChainLattice.lub_concrete_regions(a, b),
+            _ if a == b => a,
+            _ => region(ReStatic),
+        }
+    }
+}
+
+/// In `ChainLattice`, `v` would be `'b`, which contains `'a`.
+#[test]
+fn resolution_uses_the_given_lattice() {
+    let (a, b) = (free_region(0), free_region(1));
+    let mut data = RegionConstraintData::default();
+    data.constraints.insert(Constraint::RegSubVar(a, var(0)), origin());
+    data.constraints.insert(Constraint::RegSubVar(b, var(0)), origin());
+    let (var_infos, budget) = (var_infos(&[ty::UniverseIndex::ROOT]), StepBudget::new(None));
+    let mut values = initial_values(&var_infos, &data);
+    let expansion = Expansion { lattice: &FlatLattice, ..expansion(&var_infos, &data, &budget) };
+    expansion.run(&mut values);
+    assert_eq!(values.resolve_var(var(0)), region(ReStatic));
+}
+
+/// `'a <= v0 <= v1`: expansion grows each variable once, in order.
+#[test]
+fn events_replay_the_expansion() {
+    let a = free_region(0);
+    let mut data = RegionConstraintData::default();
+    data.constraints.insert(Constraint::RegSubVar(a, var(0)), origin());
+    data.constraints.insert(Constraint::VarSubVar(var(0), var(1)), origin());
+    let var_infos = var_infos(&[ty::UniverseIndex::ROOT; 2]);
+    let budget = StepBudget::new(None);
+    let events = RefCell::new(vec![]);
+    let emit = |event: ResolutionEvent<'static>| events.borrow_mut().push(event);
+    let expansion = Expansion { emit: &emit, ..expansion(&var_infos, &data, &budget) };
+    expansion.run(&mut initial_values(&var_infos, &data));
+
+    let events = events.into_inner();
+    let empty = Some(region(ReEmpty(ty::UniverseIndex::ROOT)));
+    let updated = |event: &ResolutionEvent<'_>, index| match *event {
+        ResolutionEvent::NodeUpdated { vid, from, to } => {
+            vid == var(index) && from == empty && to == Some(a)
+        }
+        _ => false,
+    };
+    assert_eq!(events.len(), 4, "{:?}", events);
+    assert!(matches!(events[0], ResolutionEvent::PhaseStart(ResolutionPhase::Expansion)));
+    assert!(updated(&events[1], 0));
+    assert!(updated(&events[2], 1));
+    assert!(matches!(
+        events[3],
+        ResolutionEvent::PhaseEnd { phase: ResolutionPhase::Expansion, iterations: 2 }
+    ));
+}
+
+/// Verifys and verify-only subregion constraints bound the region they
+/// require to be contained in something, but not the other one, and
+/// graph constraints are not considered at all.
+#[test]
+fn bounds_outside_the_graph() {
+    let a = free_region(0);
+    let re_var = |index| region(ReVar(var(index)));
+    let mut data = RegionConstraintData::default();
+    data.constraints.insert(Constraint::VarSubReg(var(0), a), origin());
+    data.verifys.push(Verify {
+        kind: GenericKind::Param(ty::ParamTy::for_self()),
+        origin: origin(),
+        region: re_var(1),
+        bound: VerifyBound::OutlivedBy(re_var(2)),
+    });
+    data.verify_subregions.push((re_var(3), re_var(4), origin()));
+
+    let bounded = vars_bounded_outside_graph(&data);
+    let mut bounded: Vec<_> = bounded.into_iter().map(|vid| vid.index()).collect();
+    bounded.sort();
+    assert_eq!(bounded, [1, 3]);
+}
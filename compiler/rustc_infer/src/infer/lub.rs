@@ -68,12 +68,7 @@ fn regions(
         debug!("{}.regions({:?}, {:?})", self.tag(), a, b);
 
         let origin = Subtype(box self.fields.trace.clone());
-        Ok(self.fields.infcx.inner.borrow_mut().unwrap_region_constraints().lub_regions(
-            self.tcx(),
-            origin,
-            a,
-            b,
-        ))
+        Ok(self.fields.infcx.lub_regions(origin, a, b))
     }
 
     fn consts(
@@ -97,6 +92,14 @@ fn binders<T>(
         // When higher-ranked types are involved, computing the LUB is
         // very challenging, switch to invariance. This is obviously
         // overly conservative but works ok in practice.
+        //
+        // (The old approach was to relate the two types with fresh
+        // region variables for the bound regions, and then replace each
+        // region in the result with a bound region if the set of regions
+        // it was related to consisted only of those fresh variables. That
+        // needs the "tainted" sets of the old region constraint
+        // collector, which are gone now that bound regions are
+        // instantiated with placeholders and handled by the leak check.)
         self.relate_with_variance(ty::Variance::Invariant, ty::VarianceDiagInfo::default(), a, b)?;
         Ok(a)
     }
@@ -10,11 +10,12 @@
 
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 use rustc_data_structures::sync::Lrc;
-use rustc_data_structures::undo_log::Rollback;
+use rustc_data_structures::undo_log::{Rollback, UndoLogs};
 use rustc_data_structures::unify as ut;
 use rustc_errors::DiagnosticBuilder;
 use rustc_hir as hir;
 use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_index::vec::IndexVec;
 use rustc_middle::infer::canonical::{Canonical, CanonicalVarValues};
 use rustc_middle::infer::unify_key::{ConstVarValue, ConstVariableValue};
 use rustc_middle::infer::unify_key::{ConstVariableOrigin, ConstVariableOriginKind, ToType};
@@ -41,7 +42,7 @@
 use self::outlives::env::OutlivesEnvironment;
 use self::region_constraints::{GenericKind, RegionConstraintData, VarInfos, VerifyBound};
 use self::region_constraints::{
-    RegionConstraintCollector, RegionConstraintStorage, RegionSnapshot,
+    RegionConstraintCollector, RegionConstraintStorage, RegionGraphStats, RegionSnapshot,
 };
 use self::type_variable::{TypeVariableOrigin, TypeVariableOriginKind};
 
@@ -56,7 +57,7 @@
 mod glb;
 mod higher_ranked;
 pub mod lattice;
-mod lexical_region_resolve;
+pub mod lexical_region_resolve;
 mod lub;
 pub mod nll_relate;
 pub mod outlives;
@@ -66,6 +67,10 @@
 pub mod type_variable;
 mod undo_log;
 
+pub use self::lexical_region_resolve::{
+    diff_solutions, RegionResolutionError, RegionResolutionErrorKind, RegionSolution, TraceStep,
+    UnboundedRegionValue, VarStatus,
+};
 use crate::infer::canonical::OriginalQueryValues;
 pub use rustc_middle::infer::unify_key;
 
@@ -261,13 +266,39 @@ fn const_unification_table(
         self.const_unification_storage.with_log(&mut self.undo_log)
     }
 
+    /// Once regions are resolved, the storage is gone, and every operation
+    /// that would add to it panics here. The panic points at the caller,
+    /// which tells which operation came too late; `lub_regions` and
+    /// `glb_regions` go through `region_constraints_for` instead, which
+    /// also names them and their regions.
     #[inline]
+    #[track_caller]
     pub fn unwrap_region_constraints(&mut self) -> RegionConstraintCollector<'_, 'tcx> {
         self.region_constraint_storage
             .as_mut()
             .expect("region constraints already solved")
             .with_log(&mut self.undo_log)
     }
+
+    /// Like `unwrap_region_constraints`, but the panic names `operation`
+    /// and the regions it was asked to combine, for operations that can be
+    /// reached long after the constraints were gathered (e.g., through a
+    /// type relation).
+    #[track_caller]
+    fn region_constraints_for(
+        &mut self,
+        operation: &str,
+        a: ty::Region<'tcx>,
+        b: ty::Region<'tcx>,
+    ) -> RegionConstraintCollector<'_, 'tcx> {
+        match self.region_constraint_storage.as_mut() {
+            Some(storage) => storage.with_log(&mut self.undo_log),
+            None => panic!(
+                "{}({:?}, {:?}) called after region constraints were solved",
+                operation, a, b
+            ),
+        }
+    }
 }
 
 pub struct InferCtxt<'a, 'tcx> {
@@ -288,6 +319,14 @@ pub struct InferCtxt<'a, 'tcx> {
     /// when entering a snapshot.
     skip_leak_check: Cell<bool>,
 
+    /// The value lexical region resolution gives to region variables that
+    /// have no upper bound at all; see `set_unbounded_region_value`.
+    unbounded_region_value: Cell<UnboundedRegionValue>,
+
+    /// The number of steps lexical region resolution may take, if
+    /// limited; see `set_region_resolution_budget`.
+    region_resolution_budget: Cell<Option<usize>>,
+
     /// Once region inference is done, the values for each variable.
     lexical_region_resolutions: RefCell<Option<LexicalRegionResolutions<'tcx>>>,
 
@@ -410,6 +449,33 @@ pub enum SubregionOrigin<'tcx> {
 #[cfg(all(target_arch = "x86_64", target_pointer_width = "64"))]
 static_assert_size!(SubregionOrigin<'_>, 32);
 
+/// A coarse grouping of `SubregionOrigin`s, so that errors arising from
+/// related constraints (say, several borrows) can be clustered when they
+/// are reported. See `SubregionOrigin::category`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RegionConstraintCategory {
+    /// Relating two types, e.g. in an assignment.
+    Subtype,
+
+    /// Borrowing or reborrowing data.
+    Borrow,
+
+    /// Reborrowing data captured by a closure.
+    ClosureCapture,
+
+    /// A type or region must outlive some region, e.g. due to a bound.
+    Bound,
+
+    /// The return type of a call must outlive the call.
+    CallReturn,
+
+    /// Checking an impl method against the trait it implements.
+    ImplMethod,
+
+    /// The constraint, if any, could not be categorized.
+    Unknown,
+}
+
 /// Times when we replace late-bound regions with variables:
 #[derive(Clone, Copy, Debug)]
 pub enum LateBoundRegionConversionTime {
@@ -590,6 +656,8 @@ pub fn enter<R>(&mut self, f: impl for<'a> FnOnce(InferCtxt<'a, 'tcx>) -> R) ->
             err_count_on_creation: tcx.sess.err_count(),
             in_snapshot: Cell::new(false),
             skip_leak_check: Cell::new(false),
+            unbounded_region_value: Cell::new(UnboundedRegionValue::default()),
+            region_resolution_budget: Cell::new(None),
             universe: Cell::new(ty::UniverseIndex::ROOT),
         })
     }
@@ -903,6 +971,72 @@ pub fn sub_regions(
         self.inner.borrow_mut().unwrap_region_constraints().make_subregion(origin, a, b);
     }
 
+    /// Returns a region that contains both `a` and `b`; see
+    /// `RegionConstraintCollector::lub_regions`. This may create a region
+    /// variable, so it panics if regions have already been resolved.
+    #[track_caller]
+    pub fn lub_regions(
+        &self,
+        origin: SubregionOrigin<'tcx>,
+        a: ty::Region<'tcx>,
+        b: ty::Region<'tcx>,
+    ) -> ty::Region<'tcx> {
+        debug!("lub_regions({:?}, {:?})", a, b);
+        self.inner
+            .borrow_mut()
+            .region_constraints_for("lub_regions", a, b)
+            .lub_regions(self.tcx, origin, a, b)
+    }
+
+    /// Returns a region that both `a` and `b` contain; see
+    /// `RegionConstraintCollector::glb_regions`. This may create a region
+    /// variable, so it panics if regions have already been resolved.
+    #[track_caller]
+    pub fn glb_regions(
+        &self,
+        origin: SubregionOrigin<'tcx>,
+        a: ty::Region<'tcx>,
+        b: ty::Region<'tcx>,
+    ) -> ty::Region<'tcx> {
+        debug!("glb_regions({:?}, {:?})", a, b);
+        self.inner
+            .borrow_mut()
+            .region_constraints_for("glb_regions", a, b)
+            .glb_regions(self.tcx, origin, a, b)
+    }
+
+    /// Require that `a` be strictly contained in `b`; see
+    /// `RegionConstraintCollector::make_strict_subregion`.
+    pub fn strict_sub_regions(
+        &self,
+        origin: SubregionOrigin<'tcx>,
+        a: ty::Region<'tcx>,
+        b: ty::Region<'tcx>,
+    ) {
+        debug!("strict_sub_regions({:?} < {:?})", a, b);
+        self.inner.borrow_mut().unwrap_region_constraints().make_strict_subregion(origin, a, b);
+    }
+
+    /// Require that the region variable `vid` be equal to `region`; see
+    /// `RegionConstraintCollector::pin_region_var`.
+    pub fn pin_region_var(
+        &self,
+        origin: SubregionOrigin<'tcx>,
+        vid: ty::RegionVid,
+        region: ty::Region<'tcx>,
+    ) {
+        debug!("pin_region_var({:?} == {:?})", vid, region);
+        self.inner.borrow_mut().unwrap_region_constraints().pin_region_var(
+            self.tcx, origin, vid, region,
+        );
+    }
+
+    /// Declares that the value of `vid` will be looked up once regions are
+    /// resolved; see `RegionConstraintCollector::mark_root`.
+    pub fn mark_region_root(&self, vid: ty::RegionVid) {
+        self.inner.borrow_mut().unwrap_region_constraints().mark_root(vid);
+    }
+
     /// Require that the region `r` be equal to one of the regions in
     /// the set `regions`.
     pub fn member_constraint(
@@ -1179,27 +1313,57 @@ pub fn resolve_regions_and_report_errors(
         outlives_env: &OutlivesEnvironment<'tcx>,
         mode: RegionckMode,
     ) {
-        let (var_infos, data) = {
+        // LUB variables are only checked against their operands when asked
+        // for: the LUB of free regions is not associative, so a variable may
+        // legitimately end up larger than the LUB of its operands' values.
+        // Erasing replaces the values with `'erased`, which cannot be
+        // compared against the operands.
+        let check_combinations = self.tcx.sess.opts.debugging_opts.check_region_combinations
+            && matches!(mode, RegionckMode::Solve);
+        let (var_infos, data, combinations) = {
             let mut inner = self.inner.borrow_mut();
             let inner = &mut *inner;
+            // The constraints gathered in a snapshot may still be rolled
+            // back, so a solution computed from them would be meaningless.
+            assert!(
+                !UndoLogs::<UndoLog<'tcx>>::in_snapshot(&inner.undo_log),
+                "cannot resolve regions during a snapshot; commit or roll it back first"
+            );
             assert!(
                 self.is_tainted_by_errors() || inner.region_obligations.is_empty(),
                 "region_obligations not empty: {:#?}",
                 inner.region_obligations
             );
-            inner
-                .region_constraint_storage
-                .take()
-                .expect("regions already resolved")
-                .with_log(&mut inner.undo_log)
-                .into_infos_and_data()
+            let mut storage =
+                inner.region_constraint_storage.take().expect("regions already resolved");
+            let combinations = if check_combinations { storage.combinations() } else { Vec::new() };
+            let (var_infos, data) = storage.with_log(&mut inner.undo_log).into_infos_and_data();
+            (var_infos, data, combinations)
         };
 
         let region_rels =
             &RegionRelations::new(self.tcx, region_context, outlives_env.free_region_map());
 
         let (lexical_region_resolutions, errors) =
-            lexical_region_resolve::resolve(region_rels, var_infos, data, mode);
+            lexical_region_resolve::resolve(
+                region_rels,
+                var_infos,
+                data,
+                mode,
+                self.unbounded_region_value.get(),
+                self.region_resolution_budget.get(),
+            );
+
+        if check_combinations {
+            if let Err(msg) =
+                lexical_region_resolutions.check_combinations(region_rels, &combinations)
+            {
+                self.tcx.sess.delay_span_bug(
+                    self.tcx.def_span(region_context),
+                    &format!("inconsistent LUB after region resolution: {}", msg),
+                );
+            }
+        }
 
         let old_value = self.lexical_region_resolutions.replace(Some(lexical_region_resolutions));
         assert!(old_value.is_none());
@@ -1214,6 +1378,153 @@ pub fn resolve_regions_and_report_errors(
         }
     }
 
+    /// Returns the errors that `resolve_regions_and_report_errors` would
+    /// report for constraints between two concrete regions; see
+    /// `RegionConstraintData::check_concrete_consistency`. Unlike
+    /// resolution, this leaves the constraints in place, so more can
+    /// still be added afterwards.
+    pub fn check_concrete_region_consistency(
+        &self,
+        region_context: DefId,
+        outlives_env: &OutlivesEnvironment<'tcx>,
+    ) -> Vec<RegionResolutionError<'tcx>> {
+        let region_rels =
+            &RegionRelations::new(self.tcx, region_context, outlives_env.free_region_map());
+        self.inner
+            .borrow_mut()
+            .unwrap_region_constraints()
+            .region_constraint_data()
+            .check_concrete_consistency(region_rels)
+    }
+
+    /// Returns true if the region constraints gathered so far imply
+    /// `r <= s`; see `RegionConstraintCollector::implies_subregion`.
+    pub fn implies_subregion(
+        &self,
+        region_context: DefId,
+        outlives_env: &OutlivesEnvironment<'tcx>,
+        r: ty::Region<'tcx>,
+        s: ty::Region<'tcx>,
+    ) -> bool {
+        let region_rels =
+            &RegionRelations::new(self.tcx, region_context, outlives_env.free_region_map());
+        self.inner.borrow_mut().unwrap_region_constraints().implies_subregion(region_rels, r, s)
+    }
+
+    /// Returns true if the region constraints gathered so far force `a`
+    /// and `b` to be equal; see `RegionConstraintCollector::vars_equated`.
+    pub fn vars_equated(&self, a: ty::RegionVid, b: ty::RegionVid) -> bool {
+        self.inner.borrow_mut().unwrap_region_constraints().vars_equated(a, b)
+    }
+
+    /// Sets the value that `resolve_regions_and_report_errors` gives to
+    /// region variables that have no upper bound at all. By default they
+    /// stay as small as their lower bounds allow; with
+    /// `UnboundedRegionValue::Static`, they become `'static` instead.
+    /// That suits variables standing for "the region this may be used
+    /// in", for which any region is fine and the largest is the most
+    /// useful.
+    pub fn set_unbounded_region_value(&self, value: UnboundedRegionValue) {
+        self.unbounded_region_value.set(value);
+    }
+
+    /// Limits the number of steps `resolve_regions_and_report_errors` may
+    /// take. Once they are used up, region inference gives up and reports
+    /// a `RegionResolutionError::Timeout` rather than carrying on. By
+    /// default (`None`), there is no limit.
+    pub fn set_region_resolution_budget(&self, budget: Option<usize>) {
+        self.region_resolution_budget.set(budget);
+    }
+
+    /// Returns `true` once the region constraints have been handed off,
+    /// either to lexical region resolution or to NLL (see
+    /// `take_region_var_origins`). After that, no more region variables
+    /// or constraints may be created, and trying to do so ICEs.
+    pub fn regions_resolved(&self) -> bool {
+        self.inner.borrow().region_constraint_storage.is_none()
+    }
+
+    /// Returns the value region inference assigned to each region
+    /// variable; see `diff_solutions`. Region inference must have been
+    /// run already (e.g., by calling `resolve_regions_and_report_errors`).
+    pub fn region_solution(&self) -> Vec<ty::Region<'tcx>> {
+        self.lexical_region_resolutions
+            .borrow()
+            .as_ref()
+            .expect("region resolution not performed")
+            .snapshot_solution()
+    }
+
+    /// Takes the result of region inference out of this context, keeping
+    /// only the value of each variable. Region inference must have been
+    /// run already, and regions can no longer be resolved through this
+    /// context (e.g., by `fully_resolve`) afterwards.
+    pub fn take_region_solution(&self) -> RegionSolution<'tcx> {
+        self.lexical_region_resolutions
+            .take()
+            .expect("region resolution not performed")
+            .into_solution()
+    }
+
+    /// Returns how region inference arrived at the value of `vid`; see
+    /// `LexicalRegionResolutions::value_trace`. This is only recorded with
+    /// `-Z trace-region-values`, and region inference must have been run
+    /// already.
+    pub fn region_value_trace(&self, vid: ty::RegionVid) -> Vec<TraceStep<'tcx>> {
+        self.lexical_region_resolutions
+            .borrow()
+            .as_ref()
+            .expect("region resolution not performed")
+            .value_trace(vid)
+            .to_vec()
+    }
+
+    /// Returns how region inference went for each region variable; see
+    /// `LexicalRegionResolutions::var_statuses`. Region inference must
+    /// have been run already.
+    pub fn region_var_statuses(&self) -> IndexVec<ty::RegionVid, VarStatus<'tcx>> {
+        self.lexical_region_resolutions
+            .borrow()
+            .as_ref()
+            .expect("region resolution not performed")
+            .var_statuses()
+    }
+
+    /// Returns how many iterations the expansion phase of region inference
+    /// took; see `LexicalRegionResolutions::expansion_iterations`. Region
+    /// inference must have been run already.
+    pub fn region_expansion_iterations(&self) -> usize {
+        self.lexical_region_resolutions
+            .borrow()
+            .as_ref()
+            .expect("region resolution not performed")
+            .expansion_iterations()
+    }
+
+    /// Returns the size of the region inference problem, with how many
+    /// iterations expansion took; see `LexicalRegionResolutions::graph_stats`.
+    /// Region inference must have been run already.
+    pub fn region_graph_stats(&self) -> Option<RegionGraphStats> {
+        self.lexical_region_resolutions
+            .borrow()
+            .as_ref()
+            .expect("region resolution not performed")
+            .graph_stats()
+    }
+
+    /// Returns the DOT snapshots taken during region inference; see
+    /// `LexicalRegionResolutions::value_frames`. These are only recorded
+    /// with `-Z dump-region-frames`, and region inference must have been
+    /// run already.
+    pub fn region_value_frames(&self) -> Vec<String> {
+        self.lexical_region_resolutions
+            .borrow()
+            .as_ref()
+            .expect("region resolution not performed")
+            .value_frames()
+            .to_vec()
+    }
+
     /// Obtains (and clears) the current set of region
     /// constraints. The inference context is still usable: further
     /// unifications will simply add new constraints.
@@ -1426,6 +1737,17 @@ pub fn replace_bound_vars_with_fresh_vars<T>(
         self.tcx.replace_bound_vars(value, fld_r, fld_t, fld_c)
     }
 
+    /// See the [`region_constraints::RegionConstraintCollector::verify_subregion`] method.
+    pub fn verify_sub_regions(
+        &self,
+        origin: SubregionOrigin<'tcx>,
+        a: ty::Region<'tcx>,
+        b: ty::Region<'tcx>,
+    ) {
+        debug!("verify_sub_regions({:?} <: {:?})", a, b);
+        self.inner.borrow_mut().unwrap_region_constraints().verify_subregion(origin, a, b);
+    }
+
     /// See the [`region_constraints::RegionConstraintCollector::verify_generic_bound`] method.
     pub fn verify_generic_bound(
         &self,
@@ -1711,6 +2033,20 @@ pub fn span(&self) -> Span {
         }
     }
 
+    pub fn category(&self) -> RegionConstraintCategory {
+        match *self {
+            Subtype(..) => RegionConstraintCategory::Subtype,
+            Reborrow(..) | DataBorrowed(..) => RegionConstraintCategory::Borrow,
+            ReborrowUpvar(..) => RegionConstraintCategory::ClosureCapture,
+            RelateObjectBound(..)
+            | RelateParamBound(..)
+            | RelateRegionParamBound(..)
+            | ReferenceOutlivesReferent(..) => RegionConstraintCategory::Bound,
+            CallReturn(..) => RegionConstraintCategory::CallReturn,
+            CompareImplMethodObligation { .. } => RegionConstraintCategory::ImplMethod,
+        }
+    }
+
     pub fn from_obligation_cause<F>(cause: &traits::ObligationCause<'tcx>, default: F) -> Self
     where
         F: FnOnce() -> Self,
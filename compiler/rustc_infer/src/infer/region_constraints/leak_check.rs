@@ -54,6 +54,15 @@ impl<'tcx> RegionConstraintCollector<'_, 'tcx> {
     ///   * if they must also be equal to a placeholder P, and U cannot name P, report an error, as that
     ///     indicates `P: R` and `R` is in an incompatible universe
     ///
+    /// # Errors
+    ///
+    /// The error returned identifies the first leak found: it is either
+    /// `RegionsInsufficientlyPolymorphic` or `RegionsOverlyPolymorphic`
+    /// (depending on `overly_polymorphic`), carrying the name of the
+    /// leaking placeholder and the region it leaked into. The set of
+    /// placeholders that are checked is the one returned by
+    /// `placeholders_since_snapshot`.
+    ///
     /// # Historical note
     ///
     /// Older variants of the leak check used to report errors for these
@@ -95,6 +104,101 @@ pub fn leak_check(
         leak_check.propagate_scc_value()?;
         Ok(())
     }
+
+    /// Returns the placeholders from universes created during `snapshot`
+    /// that appear in the region constraints added since `snapshot` was
+    /// taken, in the order they were first encountered. These are exactly
+    /// the placeholders that `leak_check` looks for leaks of.
+    pub fn placeholders_since_snapshot(
+        &self,
+        snapshot: &CombinedSnapshot<'_, 'tcx>,
+    ) -> Vec<ty::PlaceholderRegion> {
+        let mut seen = FxHashSet::default();
+        let mut placeholders = vec![];
+        let mut visit = |r: Region<'tcx>| {
+            if let ty::RePlaceholder(placeholder) = *r {
+                if snapshot.universe.cannot_name(placeholder.universe) && seen.insert(placeholder) {
+                    placeholders.push(placeholder);
+                }
+            }
+        };
+
+        for undo_entry in self.undo_log.region_constraints_in_snapshot(&snapshot.undo_snapshot) {
+            match *undo_entry {
+                AddConstraint(Constraint::RegSubVar(r, _))
+                | AddConstraint(Constraint::VarSubReg(_, r))
+                | AddGiven(r, _) => visit(r),
+                AddConstraint(Constraint::RegSubReg(a, b)) => {
+                    visit(a);
+                    visit(b);
+                }
+                AddConstraint(Constraint::VarSubVar(..))
+                | RemoveConstraint(..)
+                | AddVerify(_)
+                | AddVerifySubregion(_)
+                | AddPin(_)
+                | AddStrictSubregion(_)
+                | AddRoot(_)
+                | AddCombination(..)
+                | AddVar(_) => {}
+            }
+        }
+
+        placeholders
+    }
+
+    /// Returns the regions related to `r0`, directly or transitively and in
+    /// either direction, by the constraints added since `snapshot` was
+    /// taken. `r0` itself comes first; every other region is paired with the
+    /// constraint through which it was first reached, so that following
+    /// these back to `r0` explains why a region ended up in the set (e.g.,
+    /// how a placeholder leaked).
+    pub fn tainted_with_paths(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        snapshot: &CombinedSnapshot<'_, 'tcx>,
+        r0: Region<'tcx>,
+    ) -> Vec<(Region<'tcx>, Option<Constraint<'tcx>>)> {
+        let constraints: Vec<_> = self
+            .undo_log
+            .region_constraints_in_snapshot(&snapshot.undo_snapshot)
+            .filter_map(|undo_entry| match *undo_entry {
+                AddConstraint(constraint) if self.data.constraints.contains_key(&constraint) => {
+                    Some(constraint)
+                }
+                _ => None,
+            })
+            .collect();
+
+        let mut seen: FxHashSet<_> = Some(r0).into_iter().collect();
+        let mut result = vec![(r0, None)];
+        let mut i = 0;
+        while i < result.len() {
+            let r = result[i].0;
+            for &constraint in &constraints {
+                let (sub, sup) = match constraint {
+                    Constraint::VarSubVar(a, b) => {
+                        (tcx.mk_region(ReVar(a)), tcx.mk_region(ReVar(b)))
+                    }
+                    Constraint::RegSubVar(a, b) => (a, tcx.mk_region(ReVar(b))),
+                    Constraint::VarSubReg(a, b) => (tcx.mk_region(ReVar(a)), b),
+                    Constraint::RegSubReg(a, b) => (a, b),
+                };
+                let other = if sub == r {
+                    sup
+                } else if sup == r {
+                    sub
+                } else {
+                    continue;
+                };
+                if seen.insert(other) {
+                    result.push((other, Some(constraint)));
+                }
+            }
+            i += 1;
+        }
+        result
+    }
 }
 
 struct LeakCheck<'me, 'tcx> {
@@ -431,6 +535,15 @@ fn iterate_undo_log<'a>(
                     verifys[i].origin.span(),
                     "we never add verifications while doing higher-ranked things",
                 ),
+                // These are only checked after inference, and any failure
+                // will be reported then.
+                &AddVerifySubregion(..) | &AddPin(..) | &AddStrictSubregion(..) => {}
+                // Roots add no constraints.
+                &AddRoot(..) => {}
+                // Removing a constraint never adds an edge. (If the constraint
+                // was added in the same snapshot, we still treat it as present,
+                // which is conservative.)
+                &RemoveConstraint(..) => {}
                 &AddCombination(..) | &AddVar(..) => {}
             }
         }
@@ -3,32 +3,46 @@
 use self::CombineMapType::*;
 use self::UndoLog::*;
 
+use super::free_regions::RegionLattice;
+use super::lexical_region_resolve::{
+    construct_region_graph, var_node, RegionGraph, RegionResolutionError,
+};
 use super::{
-    InferCtxtUndoLogs, MiscVariable, RegionVariableOrigin, Rollback, Snapshot, SubregionOrigin,
+    InferCtxtUndoLogs, MiscVariable, RegionRelations, RegionVariableOrigin, Rollback, Snapshot,
+    SubregionOrigin,
 };
 
-use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet, FxHasher};
+use rustc_data_structures::graph::implementation::OUTGOING;
+use rustc_data_structures::graph::{scc::Sccs, vec_graph::VecGraph};
 use rustc_data_structures::sync::Lrc;
 use rustc_data_structures::undo_log::UndoLogs;
 use rustc_data_structures::unify as ut;
 use rustc_hir::def_id::DefId;
 use rustc_index::vec::IndexVec;
 use rustc_middle::infer::unify_key::{RegionVidKey, UnifiedRegion};
+use rustc_middle::ty::error::TypeError;
+use rustc_middle::ty::relate::RelateResult;
 use rustc_middle::ty::ReStatic;
 use rustc_middle::ty::{self, Ty, TyCtxt};
 use rustc_middle::ty::{ReLateBound, ReVar};
 use rustc_middle::ty::{Region, RegionVid};
+use rustc_span::symbol::Symbol;
 use rustc_span::Span;
 
 use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
 use std::ops::Range;
 use std::{cmp, fmt, mem};
 
 mod leak_check;
 
+#[cfg(test)]
+mod tests;
+
 pub use rustc_middle::infer::MemberConstraint;
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct RegionConstraintStorage<'tcx> {
     /// For each `RegionVid`, the corresponding `RegionVariableOrigin`.
     var_infos: IndexVec<RegionVid, RegionVariableInfo>,
@@ -45,6 +59,11 @@ pub struct RegionConstraintStorage<'tcx> {
     /// exist). This prevents us from making many such regions.
     glbs: CombineMap<'tcx>,
 
+    /// The reverse of `lubs` and `glbs`: for each "combination
+    /// variable" R3, the operation and the pair of regions (R1, R2)
+    /// that it was created for.
+    combination_origins: FxHashMap<RegionVid, (CombineMapType, TwoRegions<'tcx>)>,
+
     /// When we add a R1 == R2 constriant, we currently add (a) edges
     /// R1 <= R2 and R2 <= R1 and (b) we unify the two regions in this
     /// table. You can then call `opportunistic_resolve_var` early
@@ -58,6 +77,13 @@ pub struct RegionConstraintStorage<'tcx> {
     /// a flag set to true when we perform any unifications; this is used
     /// to micro-optimize `take_and_reset_data`
     any_unifications: bool,
+
+    /// The origins of constraints removed by `remove_constraint` during a
+    /// snapshot, indexed by their `RemoveConstraint` undo log entries, so
+    /// that rolling back can restore them. Entries whose snapshot was
+    /// committed are not needed anymore, and are dropped when the next
+    /// outermost snapshot starts.
+    removed_origins: Vec<SubregionOrigin<'tcx>>,
 }
 
 pub struct RegionConstraintCollector<'a, 'tcx> {
@@ -105,6 +131,24 @@ pub struct RegionConstraintData<'tcx> {
     /// inference variables.
     pub verifys: Vec<Verify<'tcx>>,
 
+    /// Constraints of the form `A <= B` that, like `verifys`, are only
+    /// checked once inference is done. Unlike `constraints`, they never
+    /// cause a variable to grow.
+    pub verify_subregions: Vec<(Region<'tcx>, Region<'tcx>, SubregionOrigin<'tcx>)>,
+
+    /// Region variables that must be equal to the given region; see
+    /// `RegionConstraintCollector::pin_region_var`.
+    pub pins: Vec<(RegionVid, Region<'tcx>, SubregionOrigin<'tcx>)>,
+
+    /// Pairs `(A, B)` where `A <= B` is also in `constraints`, but `A` must
+    /// not end up equal to `B`. Checked once inference is done; see
+    /// `RegionConstraintCollector::make_strict_subregion`.
+    pub strict_subregions: Vec<(Region<'tcx>, Region<'tcx>, SubregionOrigin<'tcx>)>,
+
+    /// The variables whose values the caller cares about; see
+    /// `RegionConstraintCollector::mark_root`. If empty, all of them are.
+    pub roots: Vec<RegionVid>,
+
     /// A "given" is a relationship that is known to hold. In
     /// particular, we often know from closure fn signatures that a
     /// particular free region must be a subregion of a region
@@ -126,8 +170,37 @@ pub struct RegionConstraintData<'tcx> {
     pub givens: FxHashSet<(Region<'tcx>, ty::RegionVid)>,
 }
 
+/// The size of a region inference problem; see
+/// `RegionConstraintCollector::region_graph_stats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegionGraphStats {
+    pub num_vars: usize,
+
+    /// The number of constraints of each kind.
+    pub var_sub_var: usize,
+    pub reg_sub_var: usize,
+    pub var_sub_reg: usize,
+    pub reg_sub_reg: usize,
+
+    /// The number of strongly connected components formed by the
+    /// variables and the `VarSubVar` constraints between them.
+    pub num_sccs: usize,
+
+    /// The largest number of constraints that mention any one variable.
+    pub max_degree: usize,
+
+    /// How many iterations expansion took during resolution, if the
+    /// regions have been resolved: `region_graph_stats` leaves this as
+    /// `None`, and `LexicalRegionResolutions::graph_stats` fills it in.
+    pub expansion_iterations: Option<usize>,
+}
+
 /// Represents a constraint that influences the inference process.
-#[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord)]
+///
+/// `Region`s are interned by the `TyCtxt`, so a constraint (like the keys
+/// of the LUB/GLB maps) only holds pointers to them, and each distinct
+/// region is stored once however many constraints mention it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
 pub enum Constraint<'tcx> {
     /// A region variable is a subregion of another.
     VarSubVar(RegionVid, RegionVid),
@@ -154,6 +227,15 @@ pub fn involves_placeholders(&self) -> bool {
             Constraint::RegSubReg(r, s) => r.is_placeholder() || s.is_placeholder(),
         }
     }
+
+    /// True if `vid` is one of the variables related by this constraint.
+    pub fn mentions_var(&self, vid: RegionVid) -> bool {
+        match *self {
+            Constraint::VarSubVar(a, b) => a == vid || b == vid,
+            Constraint::VarSubReg(a, _) | Constraint::RegSubVar(_, a) => a == vid,
+            Constraint::RegSubReg(..) => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -266,13 +348,22 @@ pub enum VerifyBound<'tcx> {
     AllBounds(Vec<VerifyBound<'tcx>>),
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub(crate) struct TwoRegions<'tcx> {
     a: Region<'tcx>,
     b: Region<'tcx>,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+impl<'tcx> TwoRegions<'tcx> {
+    /// LUB and GLB are both commutative, so the pair is kept in a
+    /// canonical order: `lub(a, b)` and `lub(b, a)` then share one
+    /// variable.
+    fn new(a: Region<'tcx>, b: Region<'tcx>) -> Self {
+        if a <= b { TwoRegions { a, b } } else { TwoRegions { a: b, b: a } }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(crate) enum UndoLog<'tcx> {
     /// We added `RegionVid`.
     AddVar(RegionVid),
@@ -280,9 +371,25 @@ pub(crate) enum UndoLog<'tcx> {
     /// We added the given `constraint`.
     AddConstraint(Constraint<'tcx>),
 
+    /// We removed the given `constraint`; its origin is the given entry
+    /// of `removed_origins`.
+    RemoveConstraint(Constraint<'tcx>, usize),
+
     /// We added the given `verify`.
     AddVerify(usize),
 
+    /// We added the given entry to `verify_subregions`.
+    AddVerifySubregion(usize),
+
+    /// We added the given entry to `pins`.
+    AddPin(usize),
+
+    /// We added the given entry to `strict_subregions`.
+    AddStrictSubregion(usize),
+
+    /// We added the given entry to `roots`.
+    AddRoot(usize),
+
     /// We added the given `given`.
     AddGiven(Region<'tcx>, ty::RegionVid),
 
@@ -290,8 +397,8 @@ pub(crate) enum UndoLog<'tcx> {
     AddCombination(CombineMapType, TwoRegions<'tcx>),
 }
 
-#[derive(Copy, Clone, PartialEq)]
-pub(crate) enum CombineMapType {
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CombineMapType {
     Lub,
     Glb,
 }
@@ -302,17 +409,60 @@ pub(crate) enum CombineMapType {
 pub struct RegionVariableInfo {
     pub origin: RegionVariableOrigin,
     pub universe: ty::UniverseIndex,
+
+    /// An optional name for the variable. This has no effect on
+    /// inference; it only makes debugging output easier to correlate
+    /// with the source, e.g. in macro-expanded code where the spans
+    /// of many variables overlap.
+    pub name_hint: Option<Symbol>,
 }
 
 pub struct RegionSnapshot {
     any_unifications: bool,
 }
 
+/// A full copy of a `RegionConstraintStorage`; see
+/// `RegionConstraintCollector::checkpoint`.
+pub struct RegionConstraintCheckpoint<'tcx> {
+    storage: RegionConstraintStorage<'tcx>,
+}
+
 impl<'tcx> RegionConstraintStorage<'tcx> {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Like `new`, but reserves room for `vars` region variables, of which
+    /// `combinations` are expected to be created by `lub_regions` or
+    /// `glb_regions`, so that region-heavy bodies do not keep rehashing.
+    /// (Constraints are kept in a `BTreeMap`, which never needs to be
+    /// resized, so there is nothing to reserve for them.)
+    pub fn with_capacity(vars: usize, combinations: usize) -> Self {
+        RegionConstraintStorage {
+            var_infos: IndexVec::with_capacity(vars),
+            lubs: FxHashMap::with_capacity_and_hasher(combinations, Default::default()),
+            glbs: FxHashMap::with_capacity_and_hasher(combinations, Default::default()),
+            combination_origins: FxHashMap::with_capacity_and_hasher(
+                combinations,
+                Default::default(),
+            ),
+            ..Self::default()
+        }
+    }
+
+    /// Returns each variable created by `lub_regions` or `glb_regions`,
+    /// along with the operation and the two regions it combines, ordered
+    /// by variable. See `LexicalRegionResolutions::check_combinations`.
+    pub fn combinations(&self) -> Vec<(RegionVid, CombineMapType, Region<'tcx>, Region<'tcx>)> {
+        let mut combinations: Vec<_> = self
+            .combination_origins
+            .iter()
+            .map(|(&c, &(t, TwoRegions { a, b }))| (c, t, a, b))
+            .collect();
+        combinations.sort_by_key(|&(c, ..)| c);
+        combinations
+    }
+
     #[inline]
     pub(crate) fn with_log<'a>(
         &'a mut self,
@@ -330,18 +480,43 @@ fn rollback_undo_entry(&mut self, undo_entry: UndoLog<'tcx>) {
             AddConstraint(ref constraint) => {
                 self.data.constraints.remove(constraint);
             }
+            RemoveConstraint(constraint, index) => {
+                let origin = self.removed_origins.pop().unwrap();
+                assert_eq!(self.removed_origins.len(), index);
+                self.data.constraints.insert(constraint, origin);
+            }
             AddVerify(index) => {
                 self.data.verifys.pop();
                 assert_eq!(self.data.verifys.len(), index);
             }
+            AddVerifySubregion(index) => {
+                self.data.verify_subregions.pop();
+                assert_eq!(self.data.verify_subregions.len(), index);
+            }
+            AddPin(index) => {
+                self.data.pins.pop();
+                assert_eq!(self.data.pins.len(), index);
+            }
+            AddStrictSubregion(index) => {
+                self.data.strict_subregions.pop();
+                assert_eq!(self.data.strict_subregions.len(), index);
+            }
+            AddRoot(index) => {
+                self.data.roots.pop();
+                assert_eq!(self.data.roots.len(), index);
+            }
             AddGiven(sub, sup) => {
                 self.data.givens.remove(&(sub, sup));
             }
             AddCombination(Glb, ref regions) => {
-                self.glbs.remove(regions);
+                if let Some(vid) = self.glbs.remove(regions) {
+                    self.combination_origins.remove(&vid);
+                }
             }
             AddCombination(Lub, ref regions) => {
-                self.lubs.remove(regions);
+                if let Some(vid) = self.lubs.remove(regions) {
+                    self.combination_origins.remove(&vid);
+                }
             }
         }
     }
@@ -352,12 +527,26 @@ pub fn num_region_vars(&self) -> usize {
         self.var_infos.len()
     }
 
+    /// Iterates over all region variables created so far, in order,
+    /// along with their origins.
+    pub fn region_vars(
+        &self,
+    ) -> impl Iterator<Item = (RegionVid, &RegionVariableOrigin)> + ExactSizeIterator + '_ {
+        self.var_infos.iter_enumerated().map(|(vid, info)| (vid, &info.origin))
+    }
+
     pub fn region_constraint_data(&self) -> &RegionConstraintData<'tcx> {
         &self.data
     }
 
     /// Once all the constraints have been gathered, extract out the final data.
     ///
+    /// Callers take the storage out of the inference context before calling
+    /// this, so the LUB/GLB tables are freed along with it; the resolver
+    /// then owns the constraints, and the region graph it builds, only for
+    /// the duration of resolution. Only the final variable values outlive
+    /// that, so no separate compaction step is needed.
+    ///
     /// Not legal during a snapshot.
     pub fn into_infos_and_data(self) -> (VarInfos, RegionConstraintData<'tcx>) {
         assert!(!UndoLogs::<super::UndoLog<'_>>::in_snapshot(&self.undo_log));
@@ -387,8 +576,10 @@ pub fn take_and_reset_data(&mut self) -> RegionConstraintData<'tcx> {
             data,
             lubs,
             glbs,
+            combination_origins,
             unification_table: _,
             any_unifications,
+            removed_origins,
         } = self.storage;
 
         // Clear the tables of (lubs, glbs), so that we will create
@@ -397,6 +588,8 @@ pub fn take_and_reset_data(&mut self) -> RegionConstraintData<'tcx> {
         // the one that uses this method, but it's good to be correct.
         lubs.clear();
         glbs.clear();
+        combination_origins.clear();
+        removed_origins.clear();
 
         let data = mem::take(data);
 
@@ -412,15 +605,306 @@ pub fn take_and_reset_data(&mut self) -> RegionConstraintData<'tcx> {
         data
     }
 
+    /// Removes all variables and constraints, leaving the collector as
+    /// if it had just been created, but keeping the capacity of its
+    /// tables where possible so it can be reused without reallocating.
+    ///
+    /// Not legal during a snapshot.
+    pub fn reset(&mut self) {
+        assert!(!UndoLogs::<super::UndoLog<'_>>::in_snapshot(&self.undo_log));
+
+        // As in `take_and_reset_data`, a new field needs to be handled
+        // here too.
+        let RegionConstraintStorage {
+            var_infos,
+            data,
+            lubs,
+            glbs,
+            combination_origins,
+            unification_table,
+            any_unifications,
+            removed_origins,
+        } = self.storage;
+
+        var_infos.raw.clear();
+        let RegionConstraintData {
+            constraints,
+            member_constraints,
+            verifys,
+            verify_subregions,
+            pins,
+            strict_subregions,
+            roots,
+            givens,
+        } = data;
+        constraints.clear();
+        member_constraints.clear();
+        verifys.clear();
+        verify_subregions.clear();
+        pins.clear();
+        strict_subregions.clear();
+        roots.clear();
+        givens.clear();
+        lubs.clear();
+        glbs.clear();
+        combination_origins.clear();
+        *unification_table = Default::default();
+        *any_unifications = false;
+        removed_origins.clear();
+    }
+
     pub fn data(&self) -> &RegionConstraintData<'tcx> {
         &self.data
     }
 
+    /// Returns what was recorded in the undo log for region constraints
+    /// since `snapshot` was taken, oldest first; i.e., what rolling back
+    /// to `snapshot` would undo.
+    #[cfg(test)]
+    pub(crate) fn undo_log_since(&self, snapshot: &Snapshot<'tcx>) -> Vec<UndoLog<'tcx>> {
+        self.undo_log.region_constraints_in_snapshot(snapshot).copied().collect()
+    }
+
+    /// Checks the invariants that the rest of this module relies on,
+    /// returning a description of the first one found not to hold. This
+    /// is meant for debugging: a broken invariant otherwise tends to show
+    /// up as a confusing ICE much later, e.g., during resolution.
+    pub fn validate_invariants(&self) -> Result<(), String> {
+        let num_vars = self.num_region_vars();
+        let check_vid = |vid: RegionVid, context: &dyn fmt::Debug| {
+            if vid.index() < num_vars {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{:?} refers to {:?}, but there are only {} variables",
+                    context, vid, num_vars
+                ))
+            }
+        };
+
+        for constraint in self.data.constraints.keys() {
+            let (vids, regions) = match *constraint {
+                Constraint::VarSubVar(a, b) => (vec![a, b], vec![]),
+                Constraint::RegSubVar(r, b) => (vec![b], vec![r]),
+                Constraint::VarSubReg(a, r) => (vec![a], vec![r]),
+                Constraint::RegSubReg(r, s) => (vec![], vec![r, s]),
+            };
+            for vid in vids {
+                check_vid(vid, constraint)?;
+            }
+            if regions.into_iter().any(is_var) {
+                return Err(format!(
+                    "{:?} has a variable in place of a concrete region",
+                    constraint
+                ));
+            }
+        }
+
+        for given in &self.data.givens {
+            check_vid(given.1, given)?;
+        }
+        for pin in &self.data.pins {
+            check_vid(pin.0, &(pin.0, pin.1))?;
+        }
+        for &root in &self.data.roots {
+            check_vid(root, &"root")?;
+        }
+
+        for (t, map) in [(Lub, &self.lubs), (Glb, &self.glbs)] {
+            for (pair, &c) in map {
+                check_vid(c, pair)?;
+                if self.combination_origins.get(&c) != Some(&(t, *pair)) {
+                    return Err(format!(
+                        "{:?} is the {:?} of {:?}, but its recorded origin is {:?}",
+                        c,
+                        t,
+                        pair,
+                        self.combination_origins.get(&c),
+                    ));
+                }
+            }
+        }
+
+        let mut last_var = None;
+        for undo_entry in self.undo_log.region_constraints() {
+            if let AddVar(vid) = *undo_entry {
+                if last_var.map_or(false, |last| last >= vid) {
+                    return Err(format!("{:?} was added after {:?}", vid, last_var.unwrap()));
+                }
+                last_var = Some(vid);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds the graph of the constraints gathered so far; see
+    /// `construct_region_graph` for how it is laid out.
+    ///
+    /// This is not free, so queries that can work off the constraints
+    /// themselves (e.g., `region_graph_stats`, or
+    /// `RegionConstraintData::check_concrete_consistency`) should do so;
+    /// only those that need to follow edges, like `implies_subregion`,
+    /// build the graph.
+    ///
+    /// The graph is a copy: it reflects the constraints at the time it was
+    /// built, and is not updated as constraints are added or rolled back,
+    /// so it should not be kept around past either.
+    pub fn region_graph(&self) -> RegionGraph<'tcx> {
+        construct_region_graph(self.num_region_vars(), &self.data)
+    }
+
+    /// Summarizes the size of the region inference problem gathered so
+    /// far, to help find out which bodies make region inference slow.
+    pub fn region_graph_stats(&self) -> RegionGraphStats {
+        self.data.graph_stats(self.num_region_vars())
+    }
+
+    /// Hashes the number of variables and the set of constraints gathered
+    /// so far (but not their origins), for use as a cache key. Constraints
+    /// are kept sorted, so the order they were added in does not matter.
+    /// Like any hash of regions, this is only meaningful within a single
+    /// compilation session.
+    pub fn system_hash(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        self.num_region_vars().hash(&mut hasher);
+        for constraint in self.data.constraints.keys() {
+            constraint.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Returns true if the constraints gathered so far imply `r <= s`,
+    /// whatever values region inference ends up picking. Unlike
+    /// resolution, this only follows the constraints themselves: starting
+    /// from `r`, it walks along `sub <= sup` constraints towards larger
+    /// regions, using `region_rels` to relate concrete regions.
+    pub fn implies_subregion(
+        &self,
+        region_rels: &RegionRelations<'_, 'tcx>,
+        r: Region<'tcx>,
+        s: Region<'tcx>,
+    ) -> bool {
+        let tcx = region_rels.tcx;
+        let graph = self.region_graph();
+        let mut visited = FxHashSet::default();
+        let mut stack = vec![r];
+        while let Some(sub) = stack.pop() {
+            if !visited.insert(sub) {
+                continue;
+            }
+            match *sub {
+                _ if sub == s => return true,
+                ReVar(vid) => {
+                    for (_, edge) in graph.adjacent_edges(var_node(&graph, vid), OUTGOING) {
+                        match edge.data {
+                            Constraint::VarSubVar(_, b) => stack.push(tcx.mk_region(ReVar(b))),
+                            Constraint::VarSubReg(_, b) => stack.push(b),
+                            Constraint::RegSubVar(..) | Constraint::RegSubReg(..) => {}
+                        }
+                    }
+                }
+                _ => {
+                    if !is_var(s) && region_rels.sub_concrete_regions(sub, s) {
+                        return true;
+                    }
+                    // All concrete regions share a single node in the graph,
+                    // so find the constraints whose lower bound contains
+                    // `sub` by hand.
+                    let contains = |a| region_rels.sub_concrete_regions(sub, a);
+                    for constraint in self.data.constraints.keys() {
+                        match *constraint {
+                            Constraint::RegSubVar(a, b) if contains(a) => {
+                                stack.push(tcx.mk_region(ReVar(b)))
+                            }
+                            Constraint::RegSubReg(a, b) if contains(a) => stack.push(b),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns true if the constraints gathered so far force `a` and `b`
+    /// to be equal, i.e., if each reaches the other through `VarSubVar`
+    /// constraints, so that they are in the same strongly connected
+    /// component. Like `implies_subregion`, this only looks at the
+    /// constraints, not at what resolution would infer.
+    pub fn vars_equated(&self, a: RegionVid, b: RegionVid) -> bool {
+        if a == b {
+            return true;
+        }
+        let sccs = self.var_sccs();
+        sccs.scc(a) == sccs.scc(b)
+    }
+
+    /// Returns all variables, ordered so that for every `VarSubVar(a, b)`
+    /// constraint, `a` comes before `b` unless the two are in a cycle. The
+    /// members of a cycle (i.e., of a strongly connected component) are
+    /// kept together, ordered by index, so the result is deterministic.
+    pub fn topological_order(&self) -> Vec<RegionVid> {
+        let sccs = self.var_sccs();
+        let mut vids: Vec<_> = self.var_infos.indices().collect();
+        // SCCs are numbered in post order, so an SCC reaching another one
+        // has the larger number.
+        vids.sort_by_key(|&vid| (cmp::Reverse(sccs.scc(vid)), vid));
+        vids
+    }
+
+    /// The strongly connected components of the graph of `VarSubVar`
+    /// constraints.
+    fn var_sccs(&self) -> Sccs<RegionVid, usize> {
+        let edges = self
+            .data
+            .constraints
+            .keys()
+            .filter_map(|constraint| match *constraint {
+                Constraint::VarSubVar(a, b) => Some((a, b)),
+                _ => None,
+            })
+            .collect();
+        Sccs::new(&VecGraph::new(self.num_region_vars(), edges))
+    }
+
+    /// Constraints are undone through the shared undo log, so this only
+    /// saves the little state that lives outside of it. Callers that want
+    /// to try something out should use `InferCtxt::probe`, which always
+    /// rolls back, or `InferCtxt::commit_if_ok`, rather than pairing this
+    /// with `rollback_to` by hand.
     pub fn start_snapshot(&mut self) -> RegionSnapshot {
         debug!("RegionConstraintCollector: start_snapshot");
+        if UndoLogs::<super::UndoLog<'_>>::num_open_snapshots(&self.undo_log) == 1 {
+            // Nothing from before the outermost snapshot can be rolled
+            // back anymore.
+            self.removed_origins.clear();
+        }
         RegionSnapshot { any_unifications: self.any_unifications }
     }
 
+    /// Copies all of the variables, constraints and tables gathered so
+    /// far, so that `restore` can later return to exactly this state.
+    /// Unlike a snapshot, this does not rely on the undo log, so it can be
+    /// used for backtracking over any amount of work, at the cost of a
+    /// deep copy.
+    ///
+    /// The undo log only covers changes made since the outermost snapshot
+    /// was opened, and restoring would leave such entries pointing at
+    /// state that no longer exists, so neither this nor `restore` may be
+    /// called during a snapshot.
+    pub fn checkpoint(&self) -> RegionConstraintCheckpoint<'tcx> {
+        assert!(!UndoLogs::<super::UndoLog<'_>>::in_snapshot(&self.undo_log));
+        RegionConstraintCheckpoint { storage: self.storage.clone() }
+    }
+
+    /// Returns to the state saved by `checkpoint`, discarding everything
+    /// done since.
+    pub fn restore(&mut self, checkpoint: RegionConstraintCheckpoint<'tcx>) {
+        assert!(!UndoLogs::<super::UndoLog<'_>>::in_snapshot(&self.undo_log));
+        *self.storage = checkpoint.storage;
+    }
+
     pub fn rollback_to(&mut self, snapshot: RegionSnapshot) {
         debug!("RegionConstraintCollector: rollback_to({:?})", snapshot);
         self.any_unifications = snapshot.any_unifications;
@@ -431,12 +915,35 @@ pub fn new_region_var(
         universe: ty::UniverseIndex,
         origin: RegionVariableOrigin,
     ) -> RegionVid {
-        let vid = self.var_infos.push(RegionVariableInfo { origin, universe });
+        self.new_region_var_with_hint(universe, origin, None)
+    }
+
+    /// Like `new_region_var`, but records `name` as a hint to be shown
+    /// in debugging output for the new variable.
+    pub fn new_named_region_var(
+        &mut self,
+        universe: ty::UniverseIndex,
+        origin: RegionVariableOrigin,
+        name: Symbol,
+    ) -> RegionVid {
+        self.new_region_var_with_hint(universe, origin, Some(name))
+    }
+
+    fn new_region_var_with_hint(
+        &mut self,
+        universe: ty::UniverseIndex,
+        origin: RegionVariableOrigin,
+        name_hint: Option<Symbol>,
+    ) -> RegionVid {
+        let vid = self.var_infos.push(RegionVariableInfo { origin, universe, name_hint });
 
         let u_vid = self.unification_table().new_key(UnifiedRegion(None));
         assert_eq!(vid, u_vid.vid);
         self.undo_log.push(AddVar(vid));
-        debug!("created new region variable {:?} in {:?} with origin {:?}", vid, universe, origin);
+        debug!(
+            "created new region variable {:?} in {:?} with origin {:?} and name hint {:?}",
+            vid, universe, origin, name_hint
+        );
         vid
     }
 
@@ -445,10 +952,36 @@ pub fn var_universe(&self, vid: RegionVid) -> ty::UniverseIndex {
         self.var_infos[vid].universe
     }
 
+    /// Returns the name hint for the given variable, if one was given
+    /// to `new_named_region_var`.
+    pub fn var_name_hint(&self, vid: RegionVid) -> Option<Symbol> {
+        self.var_infos[vid].name_hint
+    }
+
     fn add_constraint(&mut self, constraint: Constraint<'tcx>, origin: SubregionOrigin<'tcx>) {
         // cannot add constraints once regions are resolved
         debug!("RegionConstraintCollector: add_constraint({:?})", constraint);
 
+        // Catch variables that were not created by `new_region_var` here,
+        // rather than as an out-of-bounds index during resolution.
+        let num_vars = self.var_infos.len();
+        let check_var = |vid: RegionVid| {
+            assert!(
+                vid.index() < num_vars,
+                "constraint {:?} refers to unknown region variable {:?}",
+                constraint,
+                vid
+            );
+        };
+        match constraint {
+            Constraint::VarSubVar(a, b) => {
+                check_var(a);
+                check_var(b);
+            }
+            Constraint::RegSubVar(_, vid) | Constraint::VarSubReg(vid, _) => check_var(vid),
+            Constraint::RegSubReg(..) => {}
+        }
+
         // never overwrite an existing (constraint, origin) - only insert one if it isn't
         // present in the map yet. This prevents origins from outside the snapshot being
         // replaced with "less informative" origins e.g., during calls to `can_eq`
@@ -459,6 +992,86 @@ fn add_constraint(&mut self, constraint: Constraint<'tcx>, origin: SubregionOrig
         });
     }
 
+    /// Removes `constraint`, which must have been added before, without
+    /// affecting anything added after it. If this happens in a snapshot,
+    /// rolling the snapshot back restores the constraint with its
+    /// original origin.
+    ///
+    /// Note that the leak check still sees a constraint removed in the
+    /// snapshot it checks, if it was also added there.
+    pub fn remove_constraint(&mut self, constraint: &Constraint<'tcx>) {
+        debug!("RegionConstraintCollector: remove_constraint({:?})", constraint);
+
+        let origin = match self.storage.data.constraints.remove(constraint) {
+            Some(origin) => origin,
+            None => bug!("remove_constraint: {:?} was never added", constraint),
+        };
+        if UndoLogs::<super::UndoLog<'_>>::in_snapshot(&self.undo_log) {
+            let index = self.storage.removed_origins.len();
+            self.storage.removed_origins.push(origin);
+            self.undo_log.push(RemoveConstraint(*constraint, index));
+        }
+    }
+
+    /// Removes each `VarSubVar` constraint `a <= c` for which the other
+    /// `VarSubVar` constraints already give a path from `a` to `c`. This
+    /// leaves what every variable can reach unchanged, and hence the
+    /// result of resolution, but gives it less to do. Returns the number
+    /// of constraints removed.
+    ///
+    /// No diagnostics are lost this way: errors only ever point at the
+    /// origins of constraints with a concrete region on one side, which
+    /// are all kept.
+    pub fn simplify_constraints(&mut self) -> usize {
+        let edges: Vec<_> = self
+            .data
+            .constraints
+            .keys()
+            .filter_map(|constraint| match *constraint {
+                Constraint::VarSubVar(a, b) => Some((a, b)),
+                _ => None,
+            })
+            .collect();
+        let mut successors: FxHashMap<RegionVid, FxHashSet<RegionVid>> = FxHashMap::default();
+        for &(a, b) in &edges {
+            successors.entry(a).or_default().insert(b);
+        }
+
+        let mut removed = 0;
+        for (a, c) in edges {
+            // Look for a path from `a` to `c` that does not use `a <= c`
+            // itself. Edges removed so far are gone from `successors`, so
+            // two edges can't each be removed on account of the other.
+            let mut seen = FxHashSet::default();
+            let mut stack = vec![a];
+            let mut implied = false;
+            while let Some(v) = stack.pop() {
+                for &w in successors.get(&v).into_iter().flatten() {
+                    if (v, w) == (a, c) {
+                        continue;
+                    }
+                    if w == c {
+                        implied = true;
+                        break;
+                    }
+                    if seen.insert(w) {
+                        stack.push(w);
+                    }
+                }
+                if implied {
+                    break;
+                }
+            }
+
+            if implied {
+                successors.get_mut(&a).unwrap().remove(&c);
+                self.remove_constraint(&Constraint::VarSubVar(a, c));
+                removed += 1;
+            }
+        }
+        removed
+    }
+
     fn add_verify(&mut self, verify: Verify<'tcx>) {
         // cannot add verifys once regions are resolved
         debug!("RegionConstraintCollector: add_verify({:?})", verify);
@@ -554,6 +1167,17 @@ pub fn make_subregion(
             (_, &ReStatic) => {
                 // all regions are subregions of static, so we can ignore this
             }
+            _ if sub == sup && !is_var(sub) => {
+                // every concrete region is a subregion of itself; `'a <= 'a`
+                // for a variable is still recorded, as nothing else may
+                // mention the variable
+            }
+            (&ty::ReEmpty(ui), &ReVar(sup_id)) if self.var_universe(sup_id) <= ui => {
+                // every variable starts out as the empty region of its
+                // universe, which contains the empty regions of all the
+                // universes nested in it, so the constraint carries no
+                // information
+            }
             (&ReVar(sub_id), &ReVar(sup_id)) => {
                 self.add_constraint(Constraint::VarSubVar(sub_id, sup_id), origin);
             }
@@ -569,6 +1193,142 @@ pub fn make_subregion(
         }
     }
 
+    /// Records `sub <= sup` for each `(sub, sup)` in `pairs`, all due to
+    /// `origin`. Every pair is checked for bound regions before any of them
+    /// is recorded, so a bad entry never leaves the batch half-applied.
+    pub fn make_subregions(
+        &mut self,
+        origin: SubregionOrigin<'tcx>,
+        pairs: &[(Region<'tcx>, Region<'tcx>)],
+    ) {
+        for &(sub, sup) in pairs {
+            if matches!((sub, sup), (&ReLateBound(..), _) | (_, &ReLateBound(..))) {
+                span_bug!(origin.span(), "cannot relate bound region: {:?} <= {:?}", sub, sup);
+            }
+        }
+
+        for &(sub, sup) in pairs {
+            self.make_subregion(origin.clone(), sub, sup);
+        }
+    }
+
+    /// Like `make_subregion`, but if `sub <= sup` is already known to be
+    /// false, returns an error instead of recording a constraint that
+    /// would only fail during resolution. Only relationships that hold in
+    /// every environment are checked here, since comparing free regions
+    /// requires the free-region map, so `Ok` does not mean the constraint
+    /// will be satisfied.
+    pub fn try_make_subregion(
+        &mut self,
+        origin: SubregionOrigin<'tcx>,
+        sub: Region<'tcx>,
+        sup: Region<'tcx>,
+    ) -> RelateResult<'tcx, ()> {
+        if self.known_not_subregion(sub, sup) {
+            debug!("RegionConstraintCollector: try_make_subregion({:?} <= {:?}) fails", sub, sup);
+            return Err(TypeError::RegionsDoesNotOutlive(sup, sub));
+        }
+
+        self.make_subregion(origin, sub, sup);
+        Ok(())
+    }
+
+    /// True if `sub` and `sup` are both concrete regions and `sub <= sup`
+    /// cannot hold in any environment. The only such case is a non-empty
+    /// region being required to fit in an empty one.
+    fn known_not_subregion(&self, sub: Region<'tcx>, sup: Region<'tcx>) -> bool {
+        if is_var(sub) || is_var(sup) {
+            return false;
+        }
+
+        match (sub, sup) {
+            (&ty::ReEmpty(sub_ui), &ty::ReEmpty(sup_ui)) => sub_ui.cannot_name(sup_ui),
+            (_, &ty::ReEmpty(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Requires that `sub <= sup` hold once inference is done, without
+    /// letting this requirement influence the values inferred for any
+    /// region variables. If it does not hold, a `ConcreteFailure` is
+    /// reported.
+    pub fn verify_subregion(
+        &mut self,
+        origin: SubregionOrigin<'tcx>,
+        sub: Region<'tcx>,
+        sup: Region<'tcx>,
+    ) {
+        // cannot add verifys once regions are resolved
+        debug!("RegionConstraintCollector: verify_subregion({:?} <= {:?})", sub, sup);
+
+        if matches!((sub, sup), (&ReLateBound(..), _) | (_, &ReLateBound(..))) {
+            span_bug!(origin.span(), "cannot relate bound region: {:?} <= {:?}", sub, sup);
+        }
+
+        // skip no-op cases known to be satisfied
+        if sub == sup || *sup == ReStatic {
+            return;
+        }
+
+        let index = self.data.verify_subregions.len();
+        self.data.verify_subregions.push((sub, sup, origin));
+        self.undo_log.push(AddVerifySubregion(index));
+    }
+
+    /// Like `make_subregion`, but also requires that `sub` and `sup` not
+    /// end up being the same region. This is checked once inference is
+    /// done, and reported as a `StrictnessViolation` if it does not hold.
+    pub fn make_strict_subregion(
+        &mut self,
+        origin: SubregionOrigin<'tcx>,
+        sub: Region<'tcx>,
+        sup: Region<'tcx>,
+    ) {
+        debug!("RegionConstraintCollector: make_strict_subregion({:?} < {:?})", sub, sup);
+
+        self.make_subregion(origin.clone(), sub, sup);
+
+        let index = self.data.strict_subregions.len();
+        self.data.strict_subregions.push((sub, sup, origin));
+        self.undo_log.push(AddStrictSubregion(index));
+    }
+
+    /// Requires `vid` to be equal to `region`. This adds the constraints
+    /// `region <= vid` and `vid <= region`, but if they cannot be met,
+    /// a `PinViolation` naming the value `vid` would otherwise have had is
+    /// reported, rather than the usual `SubSupConflict`.
+    pub fn pin_region_var(
+        &mut self,
+        tcx: TyCtxt<'tcx>,
+        origin: SubregionOrigin<'tcx>,
+        vid: RegionVid,
+        region: Region<'tcx>,
+    ) {
+        debug!("RegionConstraintCollector: pin_region_var({:?} == {:?})", vid, region);
+
+        let var = tcx.mk_region(ReVar(vid));
+        self.make_subregion(origin.clone(), region, var);
+        self.make_subregion(origin.clone(), var, region);
+
+        let index = self.data.pins.len();
+        self.data.pins.push((vid, region, origin));
+        self.undo_log.push(AddPin(index));
+    }
+
+    /// Declares that the caller will look up the value of `vid` once
+    /// regions are resolved. If any variable is marked this way,
+    /// resolution only works out the values of the variables connected to
+    /// a root through `VarSubVar` constraints, in either direction. The
+    /// others are left at the empty region of their universe, whatever
+    /// `UnboundedRegionValue` is in effect, and no errors are reported
+    /// for them.
+    pub fn mark_root(&mut self, vid: RegionVid) {
+        debug!("RegionConstraintCollector: mark_root({:?})", vid);
+        let index = self.data.roots.len();
+        self.data.roots.push(vid);
+        self.undo_log.push(AddRoot(index));
+    }
+
     pub fn verify_generic_bound(
         &mut self,
         origin: SubregionOrigin<'tcx>,
@@ -588,16 +1348,31 @@ pub fn lub_regions(
     ) -> Region<'tcx> {
         // cannot add constraints once regions are resolved
         debug!("RegionConstraintCollector: lub_regions({:?}, {:?})", a, b);
+        match self.lub_without_var(a, b) {
+            Some(r) => r,
+            None => self.combine_vars(tcx, Lub, a, b, origin),
+        }
+    }
+
+    /// The part of `lub_regions` that does not need a new variable:
+    /// returns the LUB of `a` and `b` if it is one of them.
+    fn lub_without_var(&self, a: Region<'tcx>, b: Region<'tcx>) -> Option<Region<'tcx>> {
         match (a, b) {
             (r @ &ReStatic, _) | (_, r @ &ReStatic) => {
-                r // nothing lives longer than static
+                Some(r) // nothing lives longer than static
             }
 
             _ if a == b => {
-                a // LUB(a,a) = a
+                Some(a) // LUB(a,a) = a
+            }
+
+            (&ty::ReEmpty(ui), r) | (r, &ty::ReEmpty(ui))
+                if !matches!(*r, ty::ReEmpty(_)) && self.universe(r) <= ui =>
+            {
+                Some(r) // an empty region is contained in everything that can name it
             }
 
-            _ => self.combine_vars(tcx, Lub, a, b, origin),
+            _ => self.known_concrete_superregion(a, b),
         }
     }
 
@@ -610,16 +1385,51 @@ pub fn glb_regions(
     ) -> Region<'tcx> {
         // cannot add constraints once regions are resolved
         debug!("RegionConstraintCollector: glb_regions({:?}, {:?})", a, b);
+        match self.glb_without_var(a, b) {
+            Some(r) => r,
+            None => self.combine_vars(tcx, Glb, a, b, origin),
+        }
+    }
+
+    /// The part of `glb_regions` that does not need a new variable:
+    /// returns the GLB of `a` and `b` if it is one of them.
+    fn glb_without_var(&self, a: Region<'tcx>, b: Region<'tcx>) -> Option<Region<'tcx>> {
         match (a, b) {
             (&ReStatic, r) | (r, &ReStatic) => {
-                r // static lives longer than everything else
+                Some(r) // static lives longer than everything else
             }
 
             _ if a == b => {
-                a // GLB(a,a) = a
+                Some(a) // GLB(a,a) = a
             }
 
-            _ => self.combine_vars(tcx, Glb, a, b, origin),
+            (&ty::ReEmpty(ui), r) | (r, &ty::ReEmpty(ui))
+                if !matches!(*r, ty::ReEmpty(_)) && self.universe(r) <= ui =>
+            {
+                // an empty region is contained in everything that can name it
+                Some(if r == a { b } else { a })
+            }
+
+            _ => self.known_concrete_superregion(a, b).map(|r| if r == a { b } else { a }),
+        }
+    }
+
+    /// If `a` and `b` are both concrete regions and one of them is known
+    /// to contain the other, returns the larger one. This only considers
+    /// relationships that hold in every environment; comparing free
+    /// regions requires the free-region map, which isn't available until
+    /// resolution.
+    fn known_concrete_superregion(&self, a: Region<'tcx>, b: Region<'tcx>) -> Option<Region<'tcx>> {
+        if is_var(a) || is_var(b) {
+            return None;
+        }
+
+        match (a, b) {
+            // An empty region is contained in every region whose universe
+            // it can name.
+            (&ty::ReEmpty(ui), r) | (r, &ty::ReEmpty(ui)) if self.universe(r) <= ui => Some(r),
+
+            _ => None,
         }
     }
 
@@ -636,18 +1446,46 @@ pub fn opportunistic_resolve_region(
         tcx: TyCtxt<'tcx>,
         region: ty::Region<'tcx>,
     ) -> ty::Region<'tcx> {
-        match region {
-            ty::ReVar(rid) => {
-                let unified_region = self.unification_table().probe_value(*rid);
+        match as_var(region) {
+            Some(rid) => {
+                let unified_region = self.unification_table().probe_value(rid);
                 unified_region.0.unwrap_or_else(|| {
-                    let root = self.unification_table().find(*rid).vid;
+                    let root = self.unification_table().find(rid).vid;
                     tcx.reuse_or_mk_region(region, ty::ReVar(root))
                 })
             }
-            _ => region,
+            None => region,
         }
     }
 
+    /// Like `lub_regions(r, vid)`, but if `vid` has already been unified
+    /// with a concrete region, combines `r` with that region instead, which
+    /// often avoids creating a combination variable. This is sound because
+    /// unification makes the variable equal to the region, not just
+    /// bounded by it.
+    pub fn lub_region_and_var_value(
+        &mut self,
+        tcx: TyCtxt<'tcx>,
+        origin: SubregionOrigin<'tcx>,
+        r: Region<'tcx>,
+        vid: RegionVid,
+    ) -> Region<'tcx> {
+        let value = self.opportunistic_resolve_region(tcx, tcx.mk_region(ReVar(vid)));
+        self.lub_regions(tcx, origin, r, value)
+    }
+
+    /// The GLB analog of `lub_region_and_var_value`.
+    pub fn glb_region_and_var_value(
+        &mut self,
+        tcx: TyCtxt<'tcx>,
+        origin: SubregionOrigin<'tcx>,
+        r: Region<'tcx>,
+        vid: RegionVid,
+    ) -> Region<'tcx> {
+        let value = self.opportunistic_resolve_region(tcx, tcx.mk_region(ReVar(vid)));
+        self.glb_regions(tcx, origin, r, value)
+    }
+
     fn combine_map(&mut self, t: CombineMapType) -> &mut CombineMap<'tcx> {
         match t {
             Glb => &mut self.glbs,
@@ -663,7 +1501,11 @@ fn combine_vars(
         b: Region<'tcx>,
         origin: SubregionOrigin<'tcx>,
     ) -> Region<'tcx> {
-        let vars = TwoRegions { a, b };
+        // `lub_regions` and `glb_regions` handle this case themselves: the
+        // combination of a region with itself is just that region.
+        debug_assert_ne!(a, b, "combine_vars: {:?} combined with itself", a);
+
+        let vars = TwoRegions::new(a, b);
         if let Some(&c) = self.combine_map(t).get(&vars) {
             return tcx.mk_region(ReVar(c));
         }
@@ -672,9 +1514,14 @@ fn combine_vars(
         let c_universe = cmp::max(a_universe, b_universe);
         let c = self.new_region_var(c_universe, MiscVariable(origin.span()));
         self.combine_map(t).insert(vars, c);
+        self.combination_origins.insert(c, (t, vars));
         self.undo_log.push(AddCombination(t, vars));
         let new_r = tcx.mk_region(ReVar(c));
         for old_r in [a, b] {
+            // Don't add a pointless `c <= c` edge.
+            if old_r == new_r {
+                continue;
+            }
             match t {
                 Glb => self.make_subregion(origin.clone(), new_r, old_r),
                 Lub => self.make_subregion(origin.clone(), old_r, new_r),
@@ -684,6 +1531,144 @@ fn combine_vars(
         new_r
     }
 
+    /// Returns the variable that `lub_regions` (for `Lub`) or `glb_regions`
+    /// (for `Glb`) created for `a` and `b`, in either order, if any.
+    /// Unlike those, this never creates a variable.
+    pub fn existing_combination(
+        &self,
+        t: CombineMapType,
+        a: Region<'tcx>,
+        b: Region<'tcx>,
+    ) -> Option<RegionVid> {
+        let map = match t {
+            Glb => &self.glbs,
+            Lub => &self.lubs,
+        };
+        map.get(&TwoRegions::new(a, b)).copied()
+    }
+
+    /// Returns the variables created by `lub_regions` and `glb_regions`
+    /// that `solution` resolved to an empty region. No concrete region
+    /// ever reached such a variable, so the combination it stands for was
+    /// of no use to inference.
+    pub fn orphan_combination_vars(&self, solution: &[Region<'tcx>]) -> Vec<RegionVid> {
+        let mut vids: Vec<_> = self
+            .combination_origins
+            .keys()
+            .copied()
+            .filter(|vid| matches!(*solution[vid.index()], ty::ReEmpty(_)))
+            .collect();
+        vids.sort();
+        vids
+    }
+
+    /// Adds the variables and constraints gathered separately in `child`
+    /// to this collector. The child's variables are renumbered to follow
+    /// the ones already present here, and all of its constraints and
+    /// LUB/GLB tables are rewritten to match. Unifications done in the
+    /// child are not replayed, but the `<=` edges they imply are part
+    /// of its constraints, so resolution is unaffected.
+    ///
+    /// Returns the offset that was added to the child's variable indices.
+    pub fn merge_from(
+        &mut self,
+        tcx: TyCtxt<'tcx>,
+        child: &RegionConstraintStorage<'tcx>,
+    ) -> usize {
+        let offset = self.num_region_vars();
+        for info in &child.var_infos {
+            self.new_region_var_with_hint(info.universe, info.origin, info.name_hint);
+        }
+
+        let remap_vid = |vid: RegionVid| RegionVid::from(vid.index() + offset);
+        let remap = |r: Region<'tcx>| match as_var(r) {
+            Some(vid) => tcx.mk_region(ReVar(remap_vid(vid))),
+            None => r,
+        };
+
+        for (constraint, origin) in &child.data.constraints {
+            let constraint = match *constraint {
+                Constraint::VarSubVar(a, b) => Constraint::VarSubVar(remap_vid(a), remap_vid(b)),
+                Constraint::RegSubVar(a, b) => Constraint::RegSubVar(a, remap_vid(b)),
+                Constraint::VarSubReg(a, b) => Constraint::VarSubReg(remap_vid(a), b),
+                Constraint::RegSubReg(a, b) => Constraint::RegSubReg(a, b),
+            };
+            self.add_constraint(constraint, origin.clone());
+        }
+
+        for member_constraint in &child.data.member_constraints {
+            let choice_regions =
+                member_constraint.choice_regions.iter().map(|&r| remap(r)).collect();
+            self.member_constraint(
+                member_constraint.opaque_type_def_id,
+                member_constraint.definition_span,
+                tcx.fold_regions(member_constraint.hidden_ty, &mut false, |r, _| remap(r)),
+                remap(member_constraint.member_region),
+                &Lrc::new(choice_regions),
+            );
+        }
+
+        for verify in &child.data.verifys {
+            self.add_verify(Verify {
+                kind: tcx.fold_regions(verify.kind, &mut false, |r, _| remap(r)),
+                origin: verify.origin.clone(),
+                region: remap(verify.region),
+                bound: verify.bound.map_regions(tcx, &remap),
+            });
+        }
+
+        for (sub, sup, origin) in &child.data.verify_subregions {
+            self.verify_subregion(origin.clone(), remap(*sub), remap(*sup));
+        }
+
+        for &(vid, region, ref origin) in &child.data.pins {
+            let index = self.data.pins.len();
+            self.data.pins.push((remap_vid(vid), remap(region), origin.clone()));
+            self.undo_log.push(AddPin(index));
+        }
+
+        for &root in &child.data.roots {
+            let index = self.data.roots.len();
+            self.data.roots.push(remap_vid(root));
+            self.undo_log.push(AddRoot(index));
+        }
+
+        for (sub, sup, origin) in &child.data.strict_subregions {
+            let index = self.data.strict_subregions.len();
+            self.data.strict_subregions.push((remap(*sub), remap(*sup), origin.clone()));
+            self.undo_log.push(AddStrictSubregion(index));
+        }
+
+        for &(sub, sup) in &child.data.givens {
+            self.add_given(remap(sub), remap_vid(sup));
+        }
+
+        for (t, child_map) in [(Lub, &child.lubs), (Glb, &child.glbs)] {
+            for (&TwoRegions { a, b }, &c) in child_map {
+                let vars = TwoRegions::new(remap(a), remap(b));
+                if self.combine_map(t).contains_key(&vars) {
+                    continue;
+                }
+                let c = remap_vid(c);
+                self.combine_map(t).insert(vars, c);
+                self.combination_origins.insert(c, (t, vars));
+                self.undo_log.push(AddCombination(t, vars));
+            }
+        }
+
+        offset
+    }
+
+    /// If `vid` is a "combination variable" created by `lub_regions`
+    /// or `glb_regions`, returns which operation created it and the
+    /// two regions it is the LUB/GLB of.
+    pub fn combination_origin(
+        &self,
+        vid: RegionVid,
+    ) -> Option<(CombineMapType, Region<'tcx>, Region<'tcx>)> {
+        self.combination_origins.get(&vid).map(|&(t, TwoRegions { a, b })| (t, a, b))
+    }
+
     pub fn universe(&self, region: Region<'tcx>) -> ty::UniverseIndex {
         match *region {
             ty::ReStatic | ty::ReErased | ty::ReFree(..) | ty::ReEarlyBound(..) => {
@@ -727,6 +1712,18 @@ fn unification_table(&mut self) -> super::UnificationTable<'_, 'tcx, RegionVidKe
     }
 }
 
+/// Returns the variable that `r` is, if it is one.
+fn as_var(r: Region<'_>) -> Option<RegionVid> {
+    match *r {
+        ReVar(vid) => Some(vid),
+        _ => None,
+    }
+}
+
+fn is_var(r: Region<'_>) -> bool {
+    as_var(r).is_some()
+}
+
 impl fmt::Debug for RegionSnapshot {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "RegionSnapshot")
@@ -782,6 +1779,28 @@ pub fn cannot_hold(&self) -> bool {
         }
     }
 
+    /// Returns a copy of this bound with `f` applied to every region in it.
+    pub fn map_regions(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        f: &impl Fn(Region<'tcx>) -> Region<'tcx>,
+    ) -> VerifyBound<'tcx> {
+        match self {
+            VerifyBound::IfEq(ty, b) => VerifyBound::IfEq(
+                tcx.fold_regions(*ty, &mut false, |r, _| f(r)),
+                Box::new(b.map_regions(tcx, f)),
+            ),
+            VerifyBound::OutlivedBy(r) => VerifyBound::OutlivedBy(f(*r)),
+            VerifyBound::IsEmpty => VerifyBound::IsEmpty,
+            VerifyBound::AnyBound(bs) => {
+                VerifyBound::AnyBound(bs.iter().map(|b| b.map_regions(tcx, f)).collect())
+            }
+            VerifyBound::AllBounds(bs) => {
+                VerifyBound::AllBounds(bs.iter().map(|b| b.map_regions(tcx, f)).collect())
+            }
+        }
+    }
+
     pub fn or(self, vb: VerifyBound<'tcx>) -> VerifyBound<'tcx> {
         if self.must_hold() || vb.cannot_hold() {
             self
@@ -797,12 +1816,212 @@ impl<'tcx> RegionConstraintData<'tcx> {
     /// Returns `true` if this region constraint data contains no constraints, and `false`
     /// otherwise.
     pub fn is_empty(&self) -> bool {
-        let RegionConstraintData { constraints, member_constraints, verifys, givens } = self;
+        let RegionConstraintData {
+            constraints,
+            member_constraints,
+            verifys,
+            verify_subregions,
+            pins,
+            strict_subregions,
+            roots,
+            givens,
+        } = self;
         constraints.is_empty()
             && member_constraints.is_empty()
             && verifys.is_empty()
+            && verify_subregions.is_empty()
+            && pins.is_empty()
+            && strict_subregions.is_empty()
+            && roots.is_empty()
             && givens.is_empty()
     }
+
+    /// Summarizes the size of these constraints over `num_vars` variables;
+    /// see `RegionConstraintCollector::region_graph_stats`.
+    pub fn graph_stats(&self, num_vars: usize) -> RegionGraphStats {
+        let mut stats = RegionGraphStats { num_vars, ..Default::default() };
+        let mut degrees = IndexVec::<RegionVid, usize>::from_elem_n(0, num_vars);
+        let mut edges = vec![];
+        for constraint in self.constraints.keys() {
+            match *constraint {
+                Constraint::VarSubVar(a, b) => {
+                    stats.var_sub_var += 1;
+                    degrees[a] += 1;
+                    degrees[b] += 1;
+                    edges.push((a, b));
+                }
+                Constraint::RegSubVar(_, b) => {
+                    stats.reg_sub_var += 1;
+                    degrees[b] += 1;
+                }
+                Constraint::VarSubReg(a, _) => {
+                    stats.var_sub_reg += 1;
+                    degrees[a] += 1;
+                }
+                Constraint::RegSubReg(..) => stats.reg_sub_reg += 1,
+            }
+        }
+        stats.max_degree = degrees.iter().copied().max().unwrap_or(0);
+        stats.num_sccs = Sccs::<RegionVid, usize>::new(&VecGraph::new(num_vars, edges)).num_sccs();
+        stats
+    }
+
+    /// Returns an error for each constraint between two concrete regions
+    /// that does not hold. Such a constraint can never be satisfied, and
+    /// full resolution would report the same `ConcreteFailure`; this
+    /// finds them early, without resolving anything, and can be called
+    /// at any point while constraints are still being collected.
+    pub fn check_concrete_consistency(
+        &self,
+        lattice: &dyn RegionLattice<'tcx>,
+    ) -> Vec<RegionResolutionError<'tcx>> {
+        self.constraints
+            .iter()
+            .filter_map(|(constraint, origin)| match *constraint {
+                Constraint::RegSubReg(sub, sup) if !lattice.sub_concrete_regions(sub, sup) => {
+                    Some(RegionResolutionError::ConcreteFailure(origin.clone(), sub, sup))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the GLB of the concrete regions that `vid` is directly
+    /// required to be contained in, without resolving anything: `'static`
+    /// if there are none, and `None` if they have no GLB, in which case no
+    /// value of `vid` can satisfy all of them. Upper bounds that only
+    /// reach `vid` through other variables are not taken into account.
+    pub fn tightest_upper_bound(
+        &self,
+        region_rels: &RegionRelations<'_, 'tcx>,
+        vid: RegionVid,
+    ) -> Option<Region<'tcx>> {
+        let mut bound = region_rels.tcx.lifetimes.re_static;
+        for constraint in self.constraints.keys() {
+            if let Constraint::VarSubReg(a, r) = *constraint {
+                if a == vid {
+                    bound = region_rels.try_glb_concrete(bound, r).ok()?;
+                }
+            }
+        }
+        Some(bound)
+    }
+
+    /// Returns the concrete lower bound of `vid` that contains all of its
+    /// other concrete lower bounds, if there is one. Lower bounds reaching
+    /// `vid` through other variables count too, so when this returns a
+    /// region, that region alone explains the value resolution infers for
+    /// `vid` (member constraints aside). Returns `None` if `vid` has no
+    /// concrete lower bounds, or if none of them contains all the others.
+    pub fn dominant_lower_bound(
+        &self,
+        lattice: &dyn RegionLattice<'tcx>,
+        vid: RegionVid,
+    ) -> Option<Region<'tcx>> {
+        let mut visited = FxHashSet::default();
+        let mut stack = vec![vid];
+        let mut bounds = vec![];
+        while let Some(vid) = stack.pop() {
+            if !visited.insert(vid) {
+                continue;
+            }
+            for constraint in self.constraints.keys() {
+                match *constraint {
+                    Constraint::RegSubVar(r, b) if b == vid => bounds.push(r),
+                    Constraint::VarSubVar(a, b) if b == vid => stack.push(a),
+                    _ => {}
+                }
+            }
+        }
+        bounds
+            .iter()
+            .copied()
+            .find(|&candidate| bounds.iter().all(|&r| lattice.sub_concrete_regions(r, candidate)))
+    }
+
+    /// Returns every concrete region that some constraint relates, i.e.,
+    /// the regions that the values of the variables are built from.
+    pub fn concrete_regions(&self) -> FxHashSet<Region<'tcx>> {
+        let mut regions = FxHashSet::default();
+        for constraint in self.constraints.keys() {
+            match *constraint {
+                Constraint::RegSubVar(r, _) | Constraint::VarSubReg(_, r) => {
+                    regions.insert(r);
+                }
+                Constraint::RegSubReg(a, b) => {
+                    regions.insert(a);
+                    regions.insert(b);
+                }
+                Constraint::VarSubVar(..) => {}
+            }
+        }
+        regions
+    }
+
+    /// Returns the constraints that `vid` appears in, on either side, along
+    /// with their origins.
+    pub fn constraints_mentioning(
+        &self,
+        vid: RegionVid,
+    ) -> Vec<(Constraint<'tcx>, SubregionOrigin<'tcx>)> {
+        self.constraints
+            .iter()
+            .filter(|(constraint, _)| constraint.mentions_var(vid))
+            .map(|(constraint, origin)| (*constraint, origin.clone()))
+            .collect()
+    }
+
+    /// Returns the origin recorded for `constraint`, or `None` if no such
+    /// constraint was added. When a constraint is added more than once,
+    /// only the first origin is kept.
+    pub fn origin_of(&self, constraint: &Constraint<'tcx>) -> Option<SubregionOrigin<'tcx>> {
+        self.constraints.get(constraint).cloned()
+    }
+
+    /// Given the values inferred for these constraints (see
+    /// `InferCtxt::region_solution`), returns the variables that were
+    /// resolved to `'static` although they have concrete lower bounds,
+    /// none of which is `'static` itself. This is not an error, but such
+    /// variables often point at a missing relationship somewhere, which
+    /// forced inference all the way up the lattice.
+    pub fn static_resolved_vars(&self, solution: &[Region<'tcx>]) -> Vec<RegionVid> {
+        let mut lower_bounds: FxHashMap<RegionVid, bool> = FxHashMap::default();
+        for constraint in self.constraints.keys() {
+            if let Constraint::RegSubVar(sub, vid) = *constraint {
+                if !matches!(*sub, ty::ReEmpty(_)) {
+                    *lower_bounds.entry(vid).or_insert(false) |= *sub == ReStatic;
+                }
+            }
+        }
+
+        let mut vids: Vec<_> = lower_bounds
+            .into_iter()
+            .filter(|&(vid, has_static)| !has_static && *solution[vid.index()] == ReStatic)
+            .map(|(vid, _)| vid)
+            .collect();
+        vids.sort();
+        vids
+    }
+
+    /// The counterpart of `static_resolved_vars`: returns the variables
+    /// that were resolved to an empty region although they have upper
+    /// bounds. No lower bound ever reached such a variable, which is not
+    /// an error either, but can mean that a lower bound was never
+    /// recorded where it should have been.
+    pub fn empty_resolved_vars(&self, solution: &[Region<'tcx>]) -> Vec<RegionVid> {
+        let mut vids: Vec<_> = self
+            .constraints
+            .keys()
+            .filter_map(|constraint| match *constraint {
+                Constraint::VarSubVar(vid, _) | Constraint::VarSubReg(vid, _) => Some(vid),
+                Constraint::RegSubVar(..) | Constraint::RegSubReg(..) => None,
+            })
+            .filter(|vid| matches!(*solution[vid.index()], ty::ReEmpty(_)))
+            .collect();
+        vids.sort();
+        vids.dedup();
+        vids
+    }
 }
 
 impl<'tcx> Rollback<UndoLog<'tcx>> for RegionConstraintStorage<'tcx> {
@@ -0,0 +1,859 @@
+use super::*;
+use crate::infer::InferCtxtInner;
+
+use rustc_hir::def_id::CRATE_DEF_ID;
+use rustc_span::DUMMY_SP;
+use std::panic::{self, AssertUnwindSafe};
+
+// None of the code under test needs a `TyCtxt`, so the regions used here
+// are leaked rather than interned.
+fn region(kind: ty::RegionKind) -> Region<'static> {
+    Box::leak(Box::new(kind))
+}
+
+fn var(vid: RegionVid) -> Region<'static> {
+    region(ReVar(vid))
+}
+
+fn empty(universe: ty::UniverseIndex) -> Region<'static> {
+    region(ty::ReEmpty(universe))
+}
+
+fn free_region(index: u32) -> Region<'static> {
+    let scope = CRATE_DEF_ID.to_def_id();
+    region(ty::ReFree(ty::FreeRegion { scope, bound_region: ty::BrAnon(index) }))
+}
+
+/// Orders free regions by index, below `'static`. Any other region is
+/// only related to itself and `'static`.
+struct ChainLattice;
+
+impl RegionLattice<'static> for ChainLattice {
+    fn lub_concrete_regions(&self, a: Region<'static>, b: Region<'static>) -> Region<'static> {
+        let rank = |r: Region<'static>| match *r {
+            ty::ReFree(ty::FreeRegion { bound_region: ty::BrAnon(index), .. }) => Some(index),
+            ReStatic => Some(u32::MAX),
+            _ => None,
+        };
+        match (rank(a), rank(b)) {
+            _ if a == b => a,
+            (Some(a_rank), Some(b_rank)) if a_rank >= b_rank => a,
+            (Some(_), Some(_)) => b,
+            _ => region(ReStatic),
+        }
+    }
+}
+
+fn origin() -> SubregionOrigin<'static> {
+    SubregionOrigin::RelateRegionParamBound(DUMMY_SP)
+}
+
+fn new_var(
+    rc: &mut RegionConstraintCollector<'_, 'static>,
+    universe: ty::UniverseIndex,
+) -> RegionVid {
+    rc.new_region_var(universe, MiscVariable(DUMMY_SP))
+}
+
+#[test]
+fn empty_lower_bound_depends_on_universe() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let nested = root.next_universe();
+    let root_var = new_var(&mut rc, root);
+    let nested_var = new_var(&mut rc, nested);
+
+    // A variable starts out as the empty region of its universe, which
+    // contains the empty regions of nested universes...
+    rc.make_subregion(origin(), empty(root), var(root_var));
+    rc.make_subregion(origin(), empty(nested), var(root_var));
+    rc.make_subregion(origin(), empty(nested), var(nested_var));
+    assert!(rc.data().constraints.is_empty());
+
+    // ...but not the empty regions of enclosing universes.
+    rc.make_subregion(origin(), empty(root), var(nested_var));
+    let constraints: Vec<_> = rc.data().constraints.keys().cloned().collect();
+    assert_eq!(constraints, [Constraint::RegSubVar(empty(root), nested_var)]);
+}
+
+#[test]
+fn undo_log_since_snapshot() {
+    let mut inner = InferCtxtInner::new();
+    let root = ty::UniverseIndex::ROOT;
+    let outside = new_var(&mut inner.unwrap_region_constraints(), root);
+
+    let snapshot = inner.undo_log.start_snapshot();
+    let mut rc = inner.unwrap_region_constraints();
+    let inside = new_var(&mut rc, root);
+    rc.make_subregion(origin(), var(outside), var(inside));
+    rc.verify_subregion(origin(), var(inside), empty(root));
+    assert_eq!(
+        rc.undo_log_since(&snapshot),
+        [
+            AddVar(inside),
+            AddConstraint(Constraint::VarSubVar(outside, inside)),
+            AddVerifySubregion(0),
+        ]
+    );
+
+    inner.rollback_to(snapshot);
+    let rc = inner.unwrap_region_constraints();
+    assert_eq!(rc.num_region_vars(), 1);
+    assert!(rc.data().is_empty());
+}
+
+/// One step in a description of a constraint graph; see `build_from_desc`.
+/// Variables are referred to by the order in which their `Var` entries
+/// appear.
+#[derive(Copy, Clone, Debug)]
+enum ConstraintDesc {
+    /// Creates a new variable.
+    Var,
+    VarSubVar(usize, usize),
+    RegSubVar(Region<'static>, usize),
+    VarSubReg(usize, Region<'static>),
+    RegSubReg(Region<'static>, Region<'static>),
+}
+
+/// Builds the constraint graph described by `desc`, through the same
+/// `new_region_var` and `make_subregion` calls that type checking makes,
+/// so that generated graphs exercise the real code paths. Returns the
+/// variables that were created, in order.
+fn build_from_desc(
+    rc: &mut RegionConstraintCollector<'_, 'static>,
+    desc: &[ConstraintDesc],
+) -> Vec<RegionVid> {
+    let mut vars = vec![];
+    for entry in desc {
+        match *entry {
+            ConstraintDesc::Var => vars.push(new_var(rc, ty::UniverseIndex::ROOT)),
+            ConstraintDesc::VarSubVar(a, b) => {
+                rc.make_subregion(origin(), var(vars[a]), var(vars[b]))
+            }
+            ConstraintDesc::RegSubVar(r, b) => rc.make_subregion(origin(), r, var(vars[b])),
+            ConstraintDesc::VarSubReg(a, r) => rc.make_subregion(origin(), var(vars[a]), r),
+            ConstraintDesc::RegSubReg(a, b) => rc.make_subregion(origin(), a, b),
+        }
+    }
+    vars
+}
+
+/// Checks what the collector can tell about the graph described by `desc`
+/// without resolving it.
+fn check_graph(desc: &[ConstraintDesc]) {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let vars = build_from_desc(&mut rc, desc);
+    assert_eq!(rc.validate_invariants(), Ok(()), "{:?}", desc);
+
+    let order = rc.topological_order();
+    assert_eq!(order.len(), vars.len());
+    let position: FxHashMap<_, _> = order.iter().enumerate().map(|(i, &vid)| (vid, i)).collect();
+    for entry in desc {
+        if let ConstraintDesc::VarSubVar(a, b) = *entry {
+            let (a, b) = (vars[a], vars[b]);
+            assert!(position[&a] < position[&b] || rc.vars_equated(a, b), "{:?}", desc);
+        }
+    }
+}
+
+#[test]
+fn graph_from_desc_diamond() {
+    use ConstraintDesc::*;
+    let re_static = region(ReStatic);
+    check_graph(&[
+        Var,
+        Var,
+        Var,
+        Var,
+        VarSubVar(0, 1),
+        VarSubVar(0, 2),
+        VarSubVar(1, 3),
+        VarSubVar(2, 3),
+        VarSubReg(3, re_static),
+    ]);
+}
+
+#[test]
+fn graph_from_desc_cycle() {
+    use ConstraintDesc::*;
+    let desc = [Var, Var, Var, VarSubVar(0, 1), VarSubVar(1, 0), VarSubVar(1, 2)];
+    check_graph(&desc);
+
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let vars = build_from_desc(&mut rc, &desc);
+    assert!(rc.vars_equated(vars[0], vars[1]));
+    assert!(!rc.vars_equated(vars[1], vars[2]));
+}
+
+/// A small xorshift generator, so that the randomized test below is
+/// reproducible without needing a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+fn random_desc(rng: &mut Rng, len: usize) -> Vec<ConstraintDesc> {
+    let regions = [region(ReStatic), empty(ty::UniverseIndex::ROOT)];
+    let mut desc = vec![ConstraintDesc::Var];
+    let mut num_vars = 1;
+    for _ in 0..len {
+        let entry = match rng.below(5) {
+            0 => {
+                num_vars += 1;
+                ConstraintDesc::Var
+            }
+            1 => ConstraintDesc::VarSubVar(rng.below(num_vars), rng.below(num_vars)),
+            2 => ConstraintDesc::RegSubVar(regions[rng.below(2)], rng.below(num_vars)),
+            3 => ConstraintDesc::VarSubReg(rng.below(num_vars), regions[rng.below(2)]),
+            _ => ConstraintDesc::RegSubReg(regions[rng.below(2)], regions[rng.below(2)]),
+        };
+        desc.push(entry);
+    }
+    desc
+}
+
+#[test]
+fn graph_from_desc_random() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..200 {
+        check_graph(&random_desc(&mut rng, 40));
+    }
+}
+
+#[test]
+fn remove_constraint_in_snapshot() {
+    let mut inner = InferCtxtInner::new();
+    let root = ty::UniverseIndex::ROOT;
+    let (a, b) = {
+        let mut rc = inner.unwrap_region_constraints();
+        (new_var(&mut rc, root), new_var(&mut rc, root))
+    };
+    let constraint = Constraint::VarSubVar(a, b);
+    let add = |inner: &mut InferCtxtInner<'static>| {
+        inner.unwrap_region_constraints().make_subregion(origin(), var(a), var(b))
+    };
+    let start_snapshot = |inner: &mut InferCtxtInner<'static>| {
+        let snapshot = inner.undo_log.start_snapshot();
+        inner.unwrap_region_constraints().start_snapshot();
+        snapshot
+    };
+
+    // Outside of a snapshot, there is nothing to restore the constraint for.
+    add(&mut inner);
+    inner.unwrap_region_constraints().remove_constraint(&constraint);
+    assert!(inner.unwrap_region_constraints().data().constraints.is_empty());
+    assert!(inner.unwrap_region_constraints().removed_origins.is_empty());
+
+    // Rolling back restores the constraint.
+    add(&mut inner);
+    let snapshot = start_snapshot(&mut inner);
+    inner.unwrap_region_constraints().remove_constraint(&constraint);
+    assert!(inner.unwrap_region_constraints().data().constraints.is_empty());
+    inner.rollback_to(snapshot);
+    assert!(inner.unwrap_region_constraints().data().constraints.contains_key(&constraint));
+    assert!(inner.unwrap_region_constraints().removed_origins.is_empty());
+
+    // Once committed, the origin is dropped at the next snapshot.
+    let snapshot = start_snapshot(&mut inner);
+    inner.unwrap_region_constraints().remove_constraint(&constraint);
+    inner.commit(snapshot);
+    assert_eq!(inner.unwrap_region_constraints().removed_origins.len(), 1);
+    let snapshot = start_snapshot(&mut inner);
+    assert!(inner.unwrap_region_constraints().removed_origins.is_empty());
+    inner.rollback_to(snapshot);
+    assert!(inner.unwrap_region_constraints().data().constraints.is_empty());
+}
+
+fn bound_region() -> Region<'static> {
+    let br = ty::BoundRegion { var: ty::BoundVar::from_u32(0), kind: ty::BrAnon(0) };
+    region(ReLateBound(ty::INNERMOST, br))
+}
+
+#[test]
+#[should_panic(expected = "cannot relate bound region")]
+fn make_subregion_rejects_bound_regions() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let v = new_var(&mut rc, ty::UniverseIndex::ROOT);
+    rc.make_subregion(origin(), bound_region(), var(v));
+}
+
+/// A bound region that got past `make_subregion` is still caught before
+/// resolution gets to compare it with anything.
+#[test]
+#[should_panic(expected = "bound region")]
+fn region_graph_rejects_bound_regions() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let v = new_var(&mut rc, ty::UniverseIndex::ROOT);
+    rc.add_constraint(Constraint::VarSubReg(v, bound_region()), origin());
+    rc.region_graph();
+}
+
+#[test]
+fn name_hints() {
+    rustc_span::with_default_session_globals(|| {
+        let mut inner = InferCtxtInner::new();
+        let mut rc = inner.unwrap_region_constraints();
+        let root = ty::UniverseIndex::ROOT;
+        let name = Symbol::intern("'x");
+        let named = rc.new_named_region_var(root, MiscVariable(DUMMY_SP), name);
+        let unnamed = new_var(&mut rc, root);
+        assert_eq!(rc.var_name_hint(named), Some(name));
+        assert_eq!(rc.var_name_hint(unnamed), None);
+    });
+}
+
+/// A batch with a bound region in it is rejected as a whole, even if the
+/// bound region only comes after pairs that are fine.
+#[test]
+fn make_subregions_is_all_or_nothing() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let (a, b) = (new_var(&mut rc, root), new_var(&mut rc, root));
+    let re_static = region(ReStatic);
+    rc.make_subregions(origin(), &[(var(a), var(b)), (re_static, var(a))]);
+    let expected = [Constraint::VarSubVar(a, b), Constraint::RegSubVar(re_static, a)];
+    assert!(rc.data().constraints.keys().eq(expected.iter()));
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        rc.make_subregions(origin(), &[(var(b), var(a)), (bound_region(), var(a))])
+    }));
+    assert!(result.is_err());
+    assert!(rc.data().constraints.keys().eq(expected.iter()));
+}
+
+#[test]
+#[should_panic(expected = "unknown region variable")]
+fn constraints_only_mention_known_vars() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let known = new_var(&mut rc, ty::UniverseIndex::ROOT);
+    rc.make_subregion(origin(), var(known), var(RegionVid::from_u32(1)));
+}
+
+/// Each variable is a node of its own, followed by a node that is the
+/// source of all concrete lower bounds and one that is the sink of all
+/// concrete upper bounds.
+#[test]
+fn region_graph_layout() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let (a, b) = (new_var(&mut rc, root), new_var(&mut rc, root));
+    let (re_static, re_empty) = (region(ReStatic), empty(root));
+    rc.make_subregion(origin(), var(a), var(b));
+    rc.make_subregion(origin(), re_static, var(a));
+    rc.make_subregion(origin(), var(b), re_empty);
+    rc.make_subregion(origin(), re_static, re_empty);
+
+    let graph = rc.region_graph();
+    assert_eq!(graph.len_nodes(), 4);
+    let edges: Vec<_> = graph
+        .all_edges()
+        .iter()
+        .map(|edge| (edge.source().node_id(), edge.target().node_id(), edge.data))
+        .collect();
+    assert_eq!(
+        edges,
+        [
+            (0, 1, Constraint::VarSubVar(a, b)),
+            (2, 0, Constraint::RegSubVar(re_static, a)),
+            (1, 3, Constraint::VarSubReg(b, re_empty)),
+        ]
+    );
+}
+
+fn placeholder(universe: ty::UniverseIndex) -> Region<'static> {
+    region(ty::RePlaceholder(ty::PlaceholderRegion { universe, name: ty::BrAnon(0) }))
+}
+
+#[test]
+fn static_resolved_vars() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let vars: Vec<_> = (0..4).map(|_| new_var(&mut rc, root)).collect();
+    let (re_static, p) = (region(ReStatic), placeholder(root));
+    rc.make_subregion(origin(), p, var(vars[0]));
+    rc.make_subregion(origin(), p, var(vars[1]));
+    rc.make_subregion(origin(), re_static, var(vars[1]));
+    rc.make_subregion(origin(), p, var(vars[3]));
+
+    // `vars[1]` has a `'static` lower bound and `vars[2]` has no lower
+    // bounds at all, so neither of them was pushed up to `'static`.
+    let solution = [re_static, re_static, re_static, p];
+    assert_eq!(rc.data().static_resolved_vars(&solution), [vars[0]]);
+}
+
+#[test]
+fn region_vars_in_creation_order() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let misc = new_var(&mut rc, root);
+    let pattern = rc.new_region_var(root, RegionVariableOrigin::PatternRegion(DUMMY_SP));
+
+    let vars = rc.region_vars();
+    assert_eq!(vars.len(), 2);
+    let vars: Vec<_> = vars
+        .map(|(vid, origin)| (vid, matches!(origin, RegionVariableOrigin::PatternRegion(_))))
+        .collect();
+    assert_eq!(vars, [(misc, false), (pattern, true)]);
+}
+
+/// Only a non-empty region required to fit in an empty one is rejected up
+/// front; anything involving a variable is left for resolution to decide.
+#[test]
+fn try_make_subregion_rejects_known_failures() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let v = new_var(&mut rc, root);
+    let (re_static, re_empty) = (region(ReStatic), empty(root));
+
+    let result = rc.try_make_subregion(origin(), re_static, re_empty);
+    assert!(matches!(result, Err(TypeError::RegionsDoesNotOutlive(..))));
+    let result = rc.try_make_subregion(origin(), empty(root.next_universe()), re_empty);
+    assert!(matches!(result, Err(TypeError::RegionsDoesNotOutlive(..))));
+    assert!(rc.data().constraints.is_empty());
+
+    assert!(rc.try_make_subregion(origin(), var(v), re_empty).is_ok());
+    let constraints: Vec<_> = rc.data().constraints.keys().cloned().collect();
+    assert_eq!(constraints, [Constraint::VarSubReg(v, re_empty)]);
+}
+
+#[test]
+fn as_var_only_matches_variables() {
+    let v = RegionVid::from_u32(0);
+    assert_eq!(as_var(var(v)), Some(v));
+    assert!(is_var(var(v)));
+    for r in
+        [region(ReStatic), empty(ty::UniverseIndex::ROOT), placeholder(ty::UniverseIndex::ROOT)]
+    {
+        assert_eq!(as_var(r), None);
+        assert!(!is_var(r));
+    }
+}
+
+/// After a reset, variables are numbered from zero again, and nothing
+/// recorded before it is left behind.
+#[test]
+fn reset_forgets_everything() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let (a, b) = (new_var(&mut rc, root), new_var(&mut rc, root));
+    rc.make_subregion(origin(), var(a), var(b));
+    rc.make_eqregion(origin(), var(a), var(b));
+    rc.verify_subregion(origin(), var(a), empty(root));
+
+    rc.reset();
+    assert_eq!(rc.num_region_vars(), 0);
+    assert!(rc.data().is_empty());
+    assert!(!rc.any_unifications);
+    assert_eq!(new_var(&mut rc, root), a);
+    assert_eq!(rc.opportunistic_resolve_var(a), a);
+}
+
+#[test]
+fn empty_resolved_vars() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let vars: Vec<_> = (0..4).map(|_| new_var(&mut rc, root)).collect();
+    let p = placeholder(root);
+    rc.make_subregion(origin(), var(vars[0]), p);
+    rc.make_subregion(origin(), var(vars[0]), var(vars[1]));
+    rc.make_subregion(origin(), var(vars[1]), p);
+    rc.make_subregion(origin(), p, var(vars[3]));
+
+    // `vars[2]` has no bounds at all and `vars[3]` only a lower one, so
+    // only `vars[0]` is reported, once for both of its upper bounds.
+    let solution = [empty(root), p, empty(root), empty(root)];
+    assert_eq!(rc.data().empty_resolved_vars(&solution), [vars[0]]);
+}
+
+#[test]
+fn constraints_mentioning() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let (a, b, c) = (new_var(&mut rc, root), new_var(&mut rc, root), new_var(&mut rc, root));
+    let (re_static, re_empty) = (region(ReStatic), empty(root));
+    rc.make_subregion(origin(), var(a), var(b));
+    rc.make_subregion(origin(), re_static, var(c));
+    rc.make_subregion(origin(), var(b), re_empty);
+    rc.make_subregion(origin(), re_static, re_empty);
+
+    let mentioning = |vid| -> Vec<_> {
+        rc.data()
+            .constraints_mentioning(vid)
+            .into_iter()
+            .map(|(constraint, _)| constraint)
+            .collect()
+    };
+    assert_eq!(mentioning(a), [Constraint::VarSubVar(a, b)]);
+    assert_eq!(mentioning(b), [Constraint::VarSubVar(a, b), Constraint::VarSubReg(b, re_empty)]);
+    assert_eq!(mentioning(c), [Constraint::RegSubVar(re_static, c)]);
+}
+
+/// `a <= c` follows from `a <= b <= c` and goes, but of two variables
+/// that are each other's only path, neither can go.
+#[test]
+fn simplify_constraints() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let vars: Vec<_> = (0..5).map(|_| new_var(&mut rc, root)).collect();
+    let re_static = region(ReStatic);
+    rc.make_subregion(origin(), var(vars[0]), var(vars[1]));
+    rc.make_subregion(origin(), var(vars[1]), var(vars[2]));
+    rc.make_subregion(origin(), var(vars[0]), var(vars[2]));
+    rc.make_subregion(origin(), var(vars[3]), var(vars[4]));
+    rc.make_subregion(origin(), var(vars[4]), var(vars[3]));
+    rc.make_subregion(origin(), re_static, var(vars[2]));
+
+    assert_eq!(rc.simplify_constraints(), 1);
+    let constraints: Vec<_> = rc.data().constraints.keys().cloned().collect();
+    assert_eq!(
+        constraints,
+        [
+            Constraint::VarSubVar(vars[0], vars[1]),
+            Constraint::VarSubVar(vars[1], vars[2]),
+            Constraint::VarSubVar(vars[3], vars[4]),
+            Constraint::VarSubVar(vars[4], vars[3]),
+            Constraint::RegSubVar(re_static, vars[2]),
+        ]
+    );
+}
+
+#[test]
+fn concrete_regions() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let (a, b) = (new_var(&mut rc, root), new_var(&mut rc, root));
+    let (re_static, re_empty, p) = (region(ReStatic), empty(root), placeholder(root));
+    rc.make_subregion(origin(), var(a), var(b));
+    rc.make_subregion(origin(), re_static, var(a));
+    rc.make_subregion(origin(), var(b), p);
+    rc.make_subregion(origin(), re_static, p);
+    rc.make_subregion(origin(), re_empty, var(a));
+
+    // `'empty <= a` holds trivially and is never recorded.
+    let expected: FxHashSet<_> = [re_static, p].iter().cloned().collect();
+    assert_eq!(rc.data().concrete_regions(), expected);
+}
+
+#[test]
+fn with_capacity_starts_out_empty() {
+    let storage = RegionConstraintStorage::with_capacity(8, 2);
+    assert!(storage.var_infos.is_empty() && storage.var_infos.raw.capacity() >= 8);
+    assert!(storage.lubs.is_empty() && storage.lubs.capacity() >= 2);
+    assert!(storage.glbs.is_empty() && storage.glbs.capacity() >= 2);
+    assert!(storage.combination_origins.capacity() >= 2);
+    assert!(storage.data.is_empty());
+}
+
+/// Only combination variables are reported, and only those left empty.
+#[test]
+fn orphan_combination_vars() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let vars: Vec<_> = (0..4).map(|_| new_var(&mut rc, root)).collect();
+    let re_static = region(ReStatic);
+    let pair = TwoRegions { a: var(vars[0]), b: re_static };
+    rc.combination_origins.insert(vars[1], (CombineMapType::Lub, pair));
+    rc.combination_origins.insert(vars[2], (CombineMapType::Glb, pair));
+
+    let solution = [re_static, re_static, empty(root), empty(root)];
+    assert_eq!(rc.orphan_combination_vars(&solution), [vars[2]]);
+}
+
+/// A graph built before a variable was created knows nothing about it,
+/// and must not mistake it for one of its dummy nodes.
+#[test]
+#[should_panic(expected = "only built for 1 variables")]
+fn region_graph_only_knows_its_own_vars() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let old = new_var(&mut rc, root);
+    let graph = rc.region_graph();
+    assert_eq!(var_node(&graph, old).node_id(), 0);
+    let new = new_var(&mut rc, root);
+    var_node(&graph, new);
+}
+
+#[test]
+fn origin_of_keeps_the_first_origin() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let (a, b) = (new_var(&mut rc, root), new_var(&mut rc, root));
+    rc.make_subregion(origin(), var(a), var(b));
+    rc.make_subregion(SubregionOrigin::CallReturn(DUMMY_SP), var(a), var(b));
+
+    let data = rc.data();
+    let origin = data.origin_of(&Constraint::VarSubVar(a, b));
+    assert!(matches!(origin, Some(SubregionOrigin::RelateRegionParamBound(_))));
+    assert!(data.origin_of(&Constraint::VarSubVar(b, a)).is_none());
+}
+
+#[test]
+fn restore_returns_to_checkpoint() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let a = new_var(&mut rc, root);
+    let re_static = region(ReStatic);
+    rc.make_subregion(origin(), re_static, var(a));
+    let checkpoint = rc.checkpoint();
+
+    let b = new_var(&mut rc, root);
+    rc.make_eqregion(origin(), var(a), var(b));
+    rc.verify_subregion(origin(), var(b), empty(root));
+    rc.restore(checkpoint);
+
+    assert_eq!(rc.num_region_vars(), 1);
+    let constraints: Vec<_> = rc.data().constraints.keys().cloned().collect();
+    assert_eq!(constraints, [Constraint::RegSubVar(re_static, a)]);
+    assert!(rc.data().verify_subregions.is_empty());
+    assert!(!rc.any_unifications);
+    assert_eq!(new_var(&mut rc, root), b);
+}
+
+/// The hash depends on the variables and constraints, but not on the
+/// order the constraints were added in, nor on their origins.
+#[test]
+fn system_hash() {
+    let root = ty::UniverseIndex::ROOT;
+    let re_static = region(ReStatic);
+    let hash = |reversed: bool, extra_var: bool| {
+        let mut inner = InferCtxtInner::new();
+        let mut rc = inner.unwrap_region_constraints();
+        let (a, b) = (new_var(&mut rc, root), new_var(&mut rc, root));
+        if extra_var {
+            new_var(&mut rc, root);
+        }
+        let mut pairs = vec![(var(a), var(b)), (re_static, var(a))];
+        if reversed {
+            pairs.reverse();
+            rc.make_subregions(SubregionOrigin::CallReturn(DUMMY_SP), &pairs);
+        } else {
+            rc.make_subregions(origin(), &pairs);
+        }
+        let before = rc.system_hash();
+        rc.make_subregion(origin(), var(b), var(a));
+        (before, rc.system_hash())
+    };
+
+    let (before, after) = hash(false, false);
+    assert_eq!(hash(true, false), (before, after));
+    assert_ne!(before, after);
+    assert_ne!(hash(false, true).0, before);
+}
+
+#[test]
+fn reflexive_constraints_only_kept_for_vars() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let v = new_var(&mut rc, root);
+    let p = placeholder(root);
+    rc.make_subregion(origin(), p, p);
+    rc.make_subregion(origin(), empty(root), empty(root));
+    assert!(rc.data().constraints.is_empty());
+
+    rc.make_subregion(origin(), var(v), var(v));
+    let constraints: Vec<_> = rc.data().constraints.keys().cloned().collect();
+    assert_eq!(constraints, [Constraint::VarSubVar(v, v)]);
+}
+
+/// An empty region is contained in every region of a universe that can
+/// name it, variables included, so LUB and GLB with it never need a new
+/// variable. The empty regions of nested universes are the smaller ones.
+#[test]
+fn lub_and_glb_with_empty_region_need_no_var() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let nested = root.next_universe();
+    let v = new_var(&mut rc, root);
+
+    for &r in &[var(v), placeholder(root), region(ReStatic)] {
+        for &e in &[empty(root), empty(nested)] {
+            assert_eq!(rc.lub_without_var(e, r), Some(r));
+            assert_eq!(rc.lub_without_var(r, e), Some(r));
+            assert_eq!(rc.glb_without_var(e, r), Some(e));
+            assert_eq!(rc.glb_without_var(r, e), Some(e));
+        }
+    }
+    assert_eq!(rc.lub_without_var(empty(root), empty(nested)), Some(empty(root)));
+    assert_eq!(rc.glb_without_var(empty(root), empty(nested)), Some(empty(nested)));
+
+    // Neither a placeholder nor a variable of a nested universe has to
+    // contain the root empty region.
+    let nested_var = new_var(&mut rc, nested);
+    for &r in &[var(nested_var), placeholder(nested)] {
+        assert_eq!(rc.lub_without_var(empty(root), r), None);
+        assert_eq!(rc.glb_without_var(empty(root), r), None);
+    }
+    assert_eq!(rc.num_region_vars(), 2);
+}
+
+/// `'a <= v0 <= v1 <= v2`, with `v0 <= 'b` and `'a <= 'b` on the side:
+/// the variables form no cycle, and `v0` is mentioned the most. Nothing
+/// has been resolved, so there is no iteration count yet.
+#[test]
+fn region_graph_stats_of_a_chain() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let a = placeholder(root);
+    let b =
+        region(ty::RePlaceholder(ty::PlaceholderRegion { universe: root, name: ty::BrAnon(1) }));
+    let vars: Vec<_> = (0..3).map(|_| new_var(&mut rc, root)).collect();
+    rc.make_subregion(origin(), a, var(vars[0]));
+    rc.make_subregion(origin(), var(vars[0]), var(vars[1]));
+    rc.make_subregion(origin(), var(vars[1]), var(vars[2]));
+    rc.make_subregion(origin(), var(vars[0]), b);
+    rc.make_subregion(origin(), a, b);
+
+    let expected = RegionGraphStats {
+        num_vars: 3,
+        var_sub_var: 2,
+        reg_sub_var: 1,
+        var_sub_reg: 1,
+        reg_sub_reg: 1,
+        num_sccs: 3,
+        max_degree: 3,
+        expansion_iterations: None,
+    };
+    assert_eq!(rc.region_graph_stats(), expected);
+}
+
+/// Only the constraints between two concrete regions are checked, and they
+/// are left in place for resolution.
+#[test]
+fn concrete_failures_found_before_resolution() {
+    let (a, b, c) = (free_region(0), free_region(1), free_region(2));
+    let v = RegionVid::from_u32(0);
+    let mut data = RegionConstraintData::default();
+    for &constraint in &[
+        Constraint::RegSubReg(a, b),
+        Constraint::RegSubReg(b, a),
+        Constraint::RegSubReg(c, b),
+        Constraint::RegSubVar(c, v),
+        Constraint::VarSubReg(v, a),
+    ] {
+        data.constraints.insert(constraint, origin());
+    }
+
+    let failures: Vec<_> = data
+        .check_concrete_consistency(&ChainLattice)
+        .into_iter()
+        .map(|error| match error {
+            RegionResolutionError::ConcreteFailure(_, sub, sup) => (sub, sup),
+            error => panic!("unexpected error: {:?}", error),
+        })
+        .collect();
+    assert_eq!(failures, [(b, a), (c, b)]);
+    assert_eq!(data.constraints.len(), 5);
+}
+
+/// Two constraints with the same origin point at the same span; the
+/// error still tells which of them does not hold.
+#[test]
+fn failed_constraint_tells_apart_shared_origins() {
+    let (a, b) = (free_region(0), free_region(1));
+    let mut data = RegionConstraintData::default();
+    data.constraints.insert(Constraint::RegSubReg(a, b), origin());
+    data.constraints.insert(Constraint::RegSubReg(b, a), origin());
+    let errors = data.check_concrete_consistency(&ChainLattice);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].failed_constraint(), Some(Constraint::RegSubReg(b, a)));
+}
+
+/// The LUB or GLB of a variable with itself is that variable, so no new
+/// variable is needed.
+#[test]
+fn combining_a_var_with_itself_needs_no_var() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let v = var(new_var(&mut rc, ty::UniverseIndex::ROOT));
+    assert_eq!(rc.lub_without_var(v, v), Some(v));
+    assert_eq!(rc.glb_without_var(v, v), Some(v));
+}
+
+/// `lub(a, b)` and `lub(b, a)` share one variable, and so do the GLBs.
+#[test]
+fn combinations_ignore_operand_order() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let (v, c) = (var(new_var(&mut rc, root)), free_region(2));
+    assert_eq!(TwoRegions::new(v, c), TwoRegions::new(c, v));
+
+    let glb = new_var(&mut rc, root);
+    rc.glbs.insert(TwoRegions::new(c, v), glb);
+    assert_eq!(rc.existing_combination(CombineMapType::Glb, v, c), Some(glb));
+    assert_eq!(rc.existing_combination(CombineMapType::Glb, c, v), Some(glb));
+}
+
+/// `existing_combination` only looks up variables made by `lub_regions`
+/// or `glb_regions`, for the operation it is asked about.
+#[test]
+fn existing_combination_never_creates_one() {
+    let mut inner = InferCtxtInner::new();
+    let mut rc = inner.unwrap_region_constraints();
+    let root = ty::UniverseIndex::ROOT;
+    let (v, c) = (var(new_var(&mut rc, root)), free_region(2));
+    assert_eq!(rc.existing_combination(CombineMapType::Lub, v, c), None);
+    assert_eq!(rc.num_region_vars(), 1);
+
+    let lub = new_var(&mut rc, root);
+    rc.lubs.insert(TwoRegions::new(v, c), lub);
+    assert_eq!(rc.existing_combination(CombineMapType::Lub, v, c), Some(lub));
+    assert_eq!(rc.existing_combination(CombineMapType::Glb, v, c), None);
+    assert_eq!(rc.num_region_vars(), 2);
+}
+
+/// `'a <= v0 <= v1` with `'b <= v1`, and `v0 <= v2` with a placeholder
+/// `<= v2`; `v3` has no lower bounds.
+#[test]
+fn dominant_lower_bound_contains_the_others() {
+    let (a, b, p) = (free_region(0), free_region(1), placeholder(ty::UniverseIndex::ROOT));
+    let vid = RegionVid::from_u32;
+    let (v0, v1, v2, v3) = (vid(0), vid(1), vid(2), vid(3));
+    let mut data = RegionConstraintData::default();
+    for &constraint in &[
+        Constraint::RegSubVar(a, v0),
+        Constraint::RegSubVar(b, v1),
+        Constraint::VarSubVar(v0, v1),
+        Constraint::RegSubVar(p, v2),
+        Constraint::VarSubVar(v0, v2),
+    ] {
+        data.constraints.insert(constraint, origin());
+    }
+
+    let bound = |vid| data.dominant_lower_bound(&ChainLattice, vid);
+    assert_eq!(bound(v0), Some(a));
+    // `'a` reaches `v1` through `v0`, but `'b` contains it.
+    assert_eq!(bound(v1), Some(b));
+    // `'a` and the placeholder are unrelated.
+    assert_eq!(bound(v2), None);
+    assert_eq!(bound(v3), None);
+}
@@ -42,6 +42,7 @@ pub enum TypeError<'tcx> {
     ArgCount,
 
     RegionsDoesNotOutlive(Region<'tcx>, Region<'tcx>),
+    RegionsNoOverlap(Region<'tcx>, Region<'tcx>),
     RegionsInsufficientlyPolymorphic(BoundRegionKind, Region<'tcx>),
     RegionsOverlyPolymorphic(BoundRegionKind, Region<'tcx>),
     RegionsPlaceholderMismatch,
@@ -133,6 +134,7 @@ fn report_maybe_different(
             ),
             ArgCount => write!(f, "incorrect number of function parameters"),
             RegionsDoesNotOutlive(..) => write!(f, "lifetime mismatch"),
+            RegionsNoOverlap(..) => write!(f, "lifetimes do not intersect"),
             RegionsInsufficientlyPolymorphic(br, _) => write!(
                 f,
                 "expected bound lifetime parameter{}, found concrete lifetime",
@@ -222,6 +224,7 @@ pub fn must_include_note(&self) -> bool {
             | TupleSize(_)
             | ArgCount
             | RegionsDoesNotOutlive(..)
+            | RegionsNoOverlap(..)
             | RegionsInsufficientlyPolymorphic(..)
             | RegionsOverlyPolymorphic(..)
             | RegionsPlaceholderMismatch
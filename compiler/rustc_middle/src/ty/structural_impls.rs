@@ -594,6 +594,9 @@ fn lift_to_tcx(self, tcx: TyCtxt<'tcx>) -> Option<Self::Lifted> {
             RegionsDoesNotOutlive(a, b) => {
                 return tcx.lift((a, b)).map(|(a, b)| RegionsDoesNotOutlive(a, b));
             }
+            RegionsNoOverlap(a, b) => {
+                return tcx.lift((a, b)).map(|(a, b)| RegionsNoOverlap(a, b));
+            }
             RegionsInsufficientlyPolymorphic(a, b) => {
                 return tcx.lift(b).map(|b| RegionsInsufficientlyPolymorphic(a, b));
             }
@@ -1000,6 +1000,9 @@ mod parse {
     // If you add a new option, please update:
     // - compiler/rustc_interface/src/tests.rs
 
+    all_region_conflicts: bool = (false, parse_bool, [UNTRACKED],
+        "report every distinct conflict between the bounds of a region variable, not just \
+        the first one found (default: no)"),
     allow_features: Option<Vec<String>> = (None, parse_opt_comma_list, [TRACKED],
         "only allow the listed language features to be enabled in code (space separated)"),
     always_encode_mir: bool = (false, parse_bool, [TRACKED],
@@ -1021,6 +1024,9 @@ mod parse {
         "the codegen unit partitioning strategy to use"),
     chalk: bool = (false, parse_bool, [TRACKED],
         "enable the experimental Chalk-based trait solving engine"),
+    check_region_combinations: bool = (false, parse_bool, [UNTRACKED],
+        "check the value of each LUB region variable against the values of the regions it \
+        combines after lexical region resolution (default: no)"),
     codegen_backend: Option<String> = (None, parse_opt_string, [TRACKED],
         "the backend to use"),
     combine_cgu: bool = (false, parse_bool, [TRACKED],
@@ -1069,6 +1075,9 @@ mod parse {
         computed `block` spans (one span encompassing a block's terminator and \
         all statements). If `-Z instrument-coverage` is also enabled, create \
         an additional `.html` file showing the computed coverage spans."),
+    dump_region_frames: bool = (false, parse_bool, [UNTRACKED],
+        "record the constraint graph of lexical region resolution, in DOT format, after \
+        each pass of expansion (default: no)"),
     emit_future_incompat_report: bool = (false, parse_bool, [UNTRACKED],
         "emits a future-incompatibility report for lints (RFC 2834)"),
     emit_stack_sizes: bool = (false, parse_bool, [UNTRACKED],
@@ -1220,6 +1229,10 @@ mod parse {
         "enable queries of the dependency graph for regression testing (default: no)"),
     query_stats: bool = (false, parse_bool, [UNTRACKED],
         "print some statistics about the query system (default: no)"),
+    region_iteration_limit: Option<usize> = (None, parse_opt_number, [UNTRACKED],
+        "the number of iterations a fixed-point loop of lexical region resolution may take \
+        before it is deemed to not converge, which is a compiler bug (default: based on the \
+        size of the problem)"),
     relax_elf_relocations: Option<bool> = (None, parse_opt_bool, [TRACKED],
         "whether ELF relocations can be relaxed"),
     relro_level: Option<RelroLevel> = (None, parse_relro_level, [TRACKED],
@@ -1295,6 +1308,9 @@ mod parse {
         "choose the TLS model to use (`rustc --print tls-models` for details)"),
     trace_macros: bool = (false, parse_bool, [UNTRACKED],
         "for every macro invocation, print its name and arguments (default: no)"),
+    trace_region_values: bool = (false, parse_bool, [UNTRACKED],
+        "record how lexical region resolution arrived at the value of each region \
+        variable (default: no)"),
     trap_unreachable: Option<bool> = (None, parse_opt_bool, [TRACKED],
         "generate trap instructions for unreachable intrinsics (default: use target setting, usually yes)"),
     treat_err_as_bug: Option<NonZeroUsize> = (None, parse_treat_err_as_bug, [TRACKED],
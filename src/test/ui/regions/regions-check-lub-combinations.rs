@@ -0,0 +1,15 @@
+// Test that the variables `lub_regions` creates for the arms of an `if`
+// contain the LUB of whatever their operands resolve to; with
+// `-Z check-region-combinations`, region resolution ICEs if they do not.
+
+// check-pass
+// compile-flags: -Z check-region-combinations
+
+fn pick<'a, 'b: 'a>(c: bool, x: &'a u8, y: &'b u8) -> &'a u8 {
+    if c { x } else { y }
+}
+
+fn main() {
+    let (x, y) = (1, 2);
+    pick(true, &x, &y);
+}